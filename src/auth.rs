@@ -0,0 +1,523 @@
+//! Authentication - identifying the caller of each request from a bearer
+//! token or API key, HTTP Basic credentials, or a TLS client certificate, and
+//! adding the identity to the swagger context as an Authorization.
+
+use futures::{future, Future};
+use hyper::header::{HeaderValue, WWW_AUTHENTICATE};
+use hyper::http::request::Parts;
+use hyper::service::{MakeService, Service};
+use hyper::{Body, Request, Response, StatusCode};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, PoisonError};
+use swagger::auth::{api_key_from_header, from_headers, Authorization, Basic, Bearer, RcBound, Scopes};
+use swagger::context::ContextualPayload;
+use swagger::{ErrorBound, Has, XSpanIdString};
+
+use super::http;
+use super::tls;
+
+// Environment variable which, if set, is the path to a file of bearer
+// tokens/API keys, one per line, each of the form identity:token
+pub(crate) const I2C_AUTH_TOKENS_VAR: &str = "I2C_AUTH_TOKENS";
+
+// Environment variable which, if set, is the path to an htpasswd style file
+// of bcrypt hashed HTTP Basic credentials, as created by htpasswd -B
+pub(crate) const I2C_AUTH_BASIC_VAR: &str = "I2C_AUTH_BASIC";
+
+// Header an API key may be supplied in, as an alternative to a bearer token
+const API_KEY_HEADER: &str = "X-API-Key";
+
+// Paths served without authentication, as orchestrators' health and
+// readiness probes typically can't supply credentials
+const UNAUTHENTICATED_PATHS: &[&str] = &["/health", "/ready"];
+
+// Identity given to every caller when no authentication is configured, and
+// to requests for unauthenticated paths
+pub(crate) const ANONYMOUS: &str = "anonymous";
+
+// The outcome of an authentication method examining a request
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AuthResult {
+    // The request doesn't carry credentials this method handles
+    NotPresented,
+
+    // The request was authenticated as this identity
+    Identity(String),
+
+    // The request carries credentials this method handles, but they're invalid
+    Invalid,
+}
+
+// A way of authenticating requests
+pub(crate) trait AuthMethod: Send + Sync {
+    // Name of the method, used as the Authorization issuer
+    fn name(&self) -> &'static str;
+
+    // Value for the WWW-Authenticate header of a 401, if the method uses one
+    fn challenge(&self) -> Option<&'static str>;
+
+    fn authenticate(&self, req: &Parts) -> AuthResult;
+}
+
+// Reads a file of lines of the form name:secret, skipping blank lines and
+// comments (lines starting with #)
+fn read_credentials(path: &str) -> io::Result<Vec<(String, String)>> {
+    let mut creds = Vec::new();
+    for (num, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(secret)) if !name.is_empty() && !secret.is_empty() => {
+                creds.push((name.to_string(), secret.to_string()))
+            }
+            _ => warn!("Ignoring invalid line {} of {}", num + 1, path),
+        }
+    }
+    Ok(creds)
+}
+
+// Compares secrets in constant time, so the comparison doesn't reveal how
+// much of a secret was guessed correctly
+fn secrets_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && openssl::memcmp::eq(a.as_bytes(), b.as_bytes())
+}
+
+// Static bearer tokens or API keys, loaded from the I2C_AUTH_TOKENS file.  A
+// token is accepted either as "Authorization: Bearer <token>" or in an
+// X-API-Key header.
+pub(crate) struct TokenAuth {
+    tokens: Vec<(String, String)>,
+}
+
+impl TokenAuth {
+    pub(crate) fn load(path: &str) -> io::Result<TokenAuth> {
+        let tokens = read_credentials(path)?;
+        info!("Loaded {} tokens from {}", tokens.len(), path);
+        Ok(TokenAuth { tokens })
+    }
+}
+
+impl AuthMethod for TokenAuth {
+    fn name(&self) -> &'static str {
+        "token"
+    }
+
+    fn challenge(&self) -> Option<&'static str> {
+        Some("Bearer realm=\"i2cbus\"")
+    }
+
+    fn authenticate(&self, req: &Parts) -> AuthResult {
+        let token = match from_headers::<Bearer>(&req.headers) {
+            Some(bearer) => bearer.token,
+            None => match api_key_from_header(&req.headers, API_KEY_HEADER) {
+                Some(key) => key,
+                None => return AuthResult::NotPresented,
+            },
+        };
+        // Check every token, rather than stopping at a match, to avoid
+        // revealing which token matched
+        let mut identity = None;
+        for (name, secret) in &self.tokens {
+            if secrets_eq(secret, &token) {
+                identity = Some(name.clone());
+            }
+        }
+        match identity {
+            Some(identity) => AuthResult::Identity(identity),
+            None => AuthResult::Invalid,
+        }
+    }
+}
+
+// HTTP Basic authentication, against bcrypt hashed passwords loaded from the
+// I2C_AUTH_BASIC file.  As bcrypt is deliberately slow, a SHA-256 of each
+// password which has been successfully verified is cached, so subsequent
+// requests from the same user don't need to run bcrypt again.
+pub(crate) struct BasicAuth {
+    users: HashMap<String, String>,
+    verified: Mutex<HashMap<String, [u8; 32]>>,
+}
+
+impl BasicAuth {
+    pub(crate) fn load(path: &str) -> io::Result<BasicAuth> {
+        let mut users = HashMap::new();
+        for (user, hash) in read_credentials(path)? {
+            if hash.starts_with("$2") {
+                users.insert(user, hash);
+            } else {
+                warn!("Ignoring user {} in {} - password isn't bcrypt hashed", user, path);
+            }
+        }
+        info!("Loaded {} users from {}", users.len(), path);
+        Ok(BasicAuth {
+            users,
+            verified: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn verify(&self, user: &str, password: &str) -> bool {
+        let hash = match self.users.get(user) {
+            Some(hash) => hash,
+            None => return false,
+        };
+        let digest = openssl::sha::sha256(format!("{}:{}", hash, password).as_bytes());
+        if let Some(verified) = self.verified.lock().unwrap().get(user) {
+            if openssl::memcmp::eq(verified, &digest) {
+                return true;
+            }
+        }
+        match bcrypt::verify(password, hash) {
+            Ok(true) => {
+                self.verified.lock().unwrap().insert(user.to_string(), digest);
+                true
+            }
+            Ok(false) => false,
+            Err(e) => {
+                warn!("Failed to verify password for user {} {}", user, e);
+                false
+            }
+        }
+    }
+}
+
+impl AuthMethod for BasicAuth {
+    fn name(&self) -> &'static str {
+        "basic"
+    }
+
+    fn challenge(&self) -> Option<&'static str> {
+        Some("Basic realm=\"i2cbus\"")
+    }
+
+    fn authenticate(&self, req: &Parts) -> AuthResult {
+        match from_headers::<Basic>(&req.headers) {
+            Some(basic) => {
+                let password = basic.password.unwrap_or_default();
+                match self.verify(&basic.username, &password) {
+                    true => AuthResult::Identity(basic.username),
+                    false => AuthResult::Invalid,
+                }
+            }
+            None => AuthResult::NotPresented,
+        }
+    }
+}
+
+// The subject of a verified TLS client certificate (see tls::ClientCert).
+// Only used if SSL_CLIENT_CA is set, in which case the certificate has
+// already been verified when the connection was established.
+pub(crate) struct ClientCertAuth;
+
+impl AuthMethod for ClientCertAuth {
+    fn name(&self) -> &'static str {
+        "client_cert"
+    }
+
+    fn challenge(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn authenticate(&self, req: &Parts) -> AuthResult {
+        match req.extensions.get::<tls::ClientCert>() {
+            Some(cert) => AuthResult::Identity(cert.0.clone()),
+            None => AuthResult::NotPresented,
+        }
+    }
+}
+
+// Returns the identity of the caller, as added to the context by
+// Authenticator
+pub(crate) fn identity<C>(context: &C) -> String
+where
+    C: Has<Option<Authorization>>,
+{
+    match context.get() {
+        Some(auth) => auth.subject.clone(),
+        None => ANONYMOUS.to_string(),
+    }
+}
+
+// Returns the authentication methods configured by the environment.  If none
+// are configured, all requests are allowed.
+pub(crate) fn methods() -> io::Result<Vec<Box<dyn AuthMethod>>> {
+    let mut methods: Vec<Box<dyn AuthMethod>> = Vec::new();
+    if let Ok(path) = env::var(I2C_AUTH_TOKENS_VAR) {
+        methods.push(Box::new(TokenAuth::load(&path)?));
+    }
+    if let Ok(path) = env::var(I2C_AUTH_BASIC_VAR) {
+        methods.push(Box::new(BasicAuth::load(&path)?));
+    }
+    if env::var(tls::SSL_CLIENT_CA_VAR).is_ok() {
+        methods.push(Box::new(ClientCertAuth));
+    }
+    if methods.is_empty() {
+        warn!("No authentication configured - allowing all requests as {}", ANONYMOUS);
+    }
+    Ok(methods)
+}
+
+// Authenticates a request using the first method the request presents
+// credentials for.  Returns None if authentication is required and fails.
+fn authenticate(methods: &[Box<dyn AuthMethod>], req: &Parts) -> Option<Authorization> {
+    if methods.is_empty() {
+        return Some(Authorization {
+            subject: ANONYMOUS.to_string(),
+            scopes: Scopes::All,
+            issuer: None,
+        });
+    }
+    for method in methods {
+        match method.authenticate(req) {
+            AuthResult::NotPresented => continue,
+            AuthResult::Identity(subject) => {
+                return Some(Authorization {
+                    subject,
+                    scopes: Scopes::All,
+                    issuer: Some(method.name().to_string()),
+                })
+            }
+            AuthResult::Invalid => {
+                warn!("Invalid {} credentials for {} {}", method.name(), req.method, req.uri.path());
+                return None;
+            }
+        }
+    }
+    warn!("No credentials for {} {}", req.method, req.uri.path());
+    None
+}
+
+// The response to a request which fails authentication
+fn unauthorized(methods: &[Box<dyn AuthMethod>], span_id: &XSpanIdString) -> Response<Body> {
+    let body = i2cbus_api::models::I2cBusError {
+        error: Some(nix::errno::Errno::EACCES as i32),
+        description: Some("Authentication required".to_string()),
+    };
+    let mut response = Response::new(Body::from(
+        serde_json::to_string(&body).expect("Unable to serialize error"),
+    ));
+    *response.status_mut() = StatusCode::UNAUTHORIZED;
+    let headers = response.headers_mut();
+    for challenge in methods.iter().filter_map(|method| method.challenge()) {
+        headers.append(WWW_AUTHENTICATE, HeaderValue::from_static(challenge));
+    }
+    headers.insert(
+        "x-span-id",
+        HeaderValue::from_str(&span_id.0).expect("Unable to create X-Span-ID header value"),
+    );
+    headers.insert(
+        hyper::header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// Replaces swagger's MakeAllowAllAuthenticator, authenticating each request
+/// using the configured methods, and adding the caller's identity to the
+/// context.  Requests which fail authentication get a 401.
+pub struct MakeAuthenticator<T, RC> {
+    inner: T,
+    methods: Arc<Vec<Box<dyn AuthMethod>>>,
+    marker: PhantomData<RC>,
+}
+
+impl<T, RC> MakeAuthenticator<T, RC>
+where
+    RC: RcBound,
+    RC::Result: Send + 'static,
+{
+    pub(crate) fn new(inner: T, methods: Vec<Box<dyn AuthMethod>>) -> Self {
+        MakeAuthenticator {
+            inner,
+            methods: Arc::new(methods),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, SC, RC, E, ME, S, F> MakeService<&'a SC> for MakeAuthenticator<T, RC>
+where
+    RC: RcBound + Has<XSpanIdString>,
+    RC::Result: Send + 'static,
+    T: MakeService<
+        &'a SC,
+        Error = E,
+        MakeError = ME,
+        Service = S,
+        ReqBody = ContextualPayload<Body, RC::Result>,
+        ResBody = Body,
+        Future = F,
+    >,
+    S: Service<Error = E, ReqBody = ContextualPayload<Body, RC::Result>, ResBody = Body> + Send + 'static,
+    ME: ErrorBound,
+    E: ErrorBound + Send + 'static,
+    F: Future<Item = S, Error = ME> + Send + 'static,
+    S::Future: Send,
+{
+    type ReqBody = ContextualPayload<Body, RC>;
+    type ResBody = Body;
+    type Error = E;
+    type MakeError = ME;
+    type Service = Authenticator<S, RC>;
+    type Future = Box<dyn Future<Item = Self::Service, Error = ME> + Send>;
+
+    fn make_service(&mut self, service_ctx: &'a SC) -> Self::Future {
+        let methods = self.methods.clone();
+        Box::new(self.inner.make_service(service_ctx).map(move |inner| Authenticator {
+            inner: Arc::new(Mutex::new(inner)),
+            methods,
+            marker: PhantomData,
+        }))
+    }
+}
+
+// The inner service is shared with the future authenticating each request,
+// which calls it once the request is authenticated
+pub struct Authenticator<T, RC> {
+    inner: Arc<Mutex<T>>,
+    methods: Arc<Vec<Box<dyn AuthMethod>>>,
+    marker: PhantomData<RC>,
+}
+
+impl<T, RC> Service for Authenticator<T, RC>
+where
+    RC: RcBound + Has<XSpanIdString> + Send + 'static,
+    RC::Result: Send + 'static,
+    T: Service<ReqBody = ContextualPayload<Body, RC::Result>, ResBody = Body> + Send + 'static,
+    T::Error: Send + 'static,
+    T::Future: Future<Item = Response<Body>, Error = T::Error> + Send + 'static,
+{
+    type ReqBody = ContextualPayload<Body, RC>;
+    type ResBody = Body;
+    type Error = T::Error;
+    type Future = Box<dyn Future<Item = Response<Body>, Error = T::Error> + Send>;
+
+    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        let (head, body) = req.into_parts();
+        let inner = self.inner.clone();
+        let forward = move |head, body: Self::ReqBody, auth| {
+            let body = ContextualPayload {
+                inner: body.inner,
+                context: body.context.push(auth),
+            };
+            let mut inner = inner.lock().unwrap_or_else(PoisonError::into_inner);
+            inner.call(Request::from_parts(head, body))
+        };
+        if UNAUTHENTICATED_PATHS.contains(&head.uri.path()) {
+            return Box::new(forward(head, body, None));
+        }
+        // Verifying Basic credentials runs bcrypt, which is deliberately slow,
+        // so authenticate on the blocking pool rather than holding up every
+        // other connection
+        let methods = self.methods.clone();
+        let authenticated = http::blocking(move || {
+            let auth = authenticate(&methods, &head);
+            (head, auth, methods)
+        });
+        Box::new(authenticated.and_then(move |(head, auth, methods)| match auth {
+            Some(auth) => future::Either::A(forward(head, body, Some(auth))),
+            None => future::Either::B(future::ok(unauthorized(&methods, body.context.get()))),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(header: Option<(&str, &str)>) -> Parts {
+        let mut req = Request::builder();
+        req.uri("/i2c/buslist");
+        if let Some((name, value)) = header {
+            req.header(name, value);
+        }
+        req.body(()).unwrap().into_parts().0
+    }
+
+    fn tokens() -> TokenAuth {
+        TokenAuth {
+            tokens: vec![
+                ("alice".to_string(), "secret-a".to_string()),
+                ("bob".to_string(), "secret-b".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn credentials_skip_comments_and_invalid_lines() {
+        let path = env::temp_dir().join(format!("i2cbus-test-creds-{}", std::process::id()));
+        fs::write(&path, "# comment\n\nalice:a:b\nnocolon\n:nouser\nbob:\n  carol:c  \n").unwrap();
+        let creds = read_credentials(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            creds,
+            vec![
+                ("alice".to_string(), "a:b".to_string()),
+                ("carol".to_string(), "c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_authenticate_as_bearer_or_api_key() {
+        let auth = tokens();
+        let bearer = parts(Some(("Authorization", "Bearer secret-b")));
+        assert_eq!(auth.authenticate(&bearer), AuthResult::Identity("bob".to_string()));
+        let key = parts(Some((API_KEY_HEADER, "secret-a")));
+        assert_eq!(auth.authenticate(&key), AuthResult::Identity("alice".to_string()));
+        let wrong = parts(Some(("Authorization", "Bearer secret-")));
+        assert_eq!(auth.authenticate(&wrong), AuthResult::Invalid);
+        assert_eq!(auth.authenticate(&parts(None)), AuthResult::NotPresented);
+    }
+
+    #[test]
+    fn authenticate_uses_presented_method() {
+        assert_eq!(authenticate(&[], &parts(None)).unwrap().subject, ANONYMOUS);
+        let methods: Vec<Box<dyn AuthMethod>> = vec![Box::new(tokens()), Box::new(ClientCertAuth)];
+        let auth = authenticate(&methods, &parts(Some((API_KEY_HEADER, "secret-a")))).unwrap();
+        assert_eq!(auth.subject, "alice");
+        assert_eq!(auth.issuer, Some("token".to_string()));
+        assert!(authenticate(&methods, &parts(None)).is_none());
+        assert!(authenticate(&methods, &parts(Some((API_KEY_HEADER, "guess")))).is_none());
+    }
+
+    #[test]
+    fn unauthorized_challenges_for_each_method() {
+        let methods: Vec<Box<dyn AuthMethod>> = vec![Box::new(tokens()), Box::new(ClientCertAuth)];
+        let response = unauthorized(&methods, &XSpanIdString("span".to_string()));
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let challenges: Vec<_> = response.headers().get_all(WWW_AUTHENTICATE).iter().collect();
+        assert_eq!(challenges, vec!["Bearer realm=\"i2cbus\""]);
+        assert_eq!(response.headers()["x-span-id"], "span");
+    }
+
+    #[test]
+    fn basic_auth_verifies_bcrypt_passwords() {
+        let mut users = HashMap::new();
+        users.insert("alice".to_string(), bcrypt::hash("password", 4).unwrap());
+        let auth = BasicAuth {
+            users,
+            verified: Mutex::new(HashMap::new()),
+        };
+        let basic = |credentials: &str| {
+            let header = format!("Basic {}", openssl::base64::encode_block(credentials.as_bytes()));
+            auth.authenticate(&parts(Some(("Authorization", &header))))
+        };
+        assert_eq!(basic("alice:wrong"), AuthResult::Invalid);
+        assert!(auth.verified.lock().unwrap().is_empty());
+        assert_eq!(basic("alice:password"), AuthResult::Identity("alice".to_string()));
+        assert!(auth.verified.lock().unwrap().contains_key("alice"));
+        // A cached password doesn't let a different one through
+        assert_eq!(basic("alice:password2"), AuthResult::Invalid);
+        assert_eq!(basic("alice:password"), AuthResult::Identity("alice".to_string()));
+        assert_eq!(basic("bob:password"), AuthResult::Invalid);
+        assert_eq!(auth.authenticate(&parts(None)), AuthResult::NotPresented);
+    }
+}
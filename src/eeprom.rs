@@ -0,0 +1,444 @@
+//! 24Cxx-family EEPROMs - their geometries, reading and programming them, and
+//! converting images to and from Intel HEX.
+//!
+//! Programming writes a page at a time, as a write which crosses a page
+//! boundary wraps around within the page, and after each page waits for the
+//! device to complete its write cycle by ACK polling - the device doesn't ACK
+//! its address until the cycle is complete.
+
+use super::i2c::{Bus, BusError, ChunkError, Chunking, ProbeMode, RegPointer};
+use std::cmp::min;
+use std::fmt::Write;
+use std::time::Duration;
+
+// The organisation of a type of EEPROM, named as by the kernel's at24
+// driver.  Parts larger than their register pointer can address take the
+// high bits of the memory address in the low bits of the slave address, so
+// occupy 2^block_bits consecutive addresses.  Page sizes are the smallest
+// common to the manufacturers' parts, as writing less than a page is always
+// safe.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Geometry {
+    pub name: &'static str,
+    pub size: usize,
+    pub page: usize,
+    pub pointer_width: usize,
+    pub block_bits: u32,
+}
+
+pub(crate) const GEOMETRIES: &[Geometry] = &[
+    Geometry { name: "24c01", size: 128, page: 8, pointer_width: 1, block_bits: 0 },
+    Geometry { name: "24c02", size: 256, page: 8, pointer_width: 1, block_bits: 0 },
+    Geometry { name: "24c04", size: 512, page: 16, pointer_width: 1, block_bits: 1 },
+    Geometry { name: "24c08", size: 1024, page: 16, pointer_width: 1, block_bits: 2 },
+    Geometry { name: "24c16", size: 2048, page: 16, pointer_width: 1, block_bits: 3 },
+    Geometry { name: "24c32", size: 4096, page: 32, pointer_width: 2, block_bits: 0 },
+    Geometry { name: "24c64", size: 8192, page: 32, pointer_width: 2, block_bits: 0 },
+    Geometry { name: "24c128", size: 16384, page: 64, pointer_width: 2, block_bits: 0 },
+    Geometry { name: "24c256", size: 32768, page: 64, pointer_width: 2, block_bits: 0 },
+    Geometry { name: "24c512", size: 65536, page: 128, pointer_width: 2, block_bits: 0 },
+    Geometry { name: "24c1024", size: 131_072, page: 128, pointer_width: 2, block_bits: 1 },
+    Geometry { name: "24c2048", size: 262_144, page: 256, pointer_width: 2, block_bits: 2 },
+];
+
+pub(crate) fn geometry(name: &str) -> Option<&'static Geometry> {
+    GEOMETRIES.iter().find(|geometry| geometry.name == name)
+}
+
+impl Geometry {
+    // Number of slave addresses the device occupies
+    pub(crate) fn blocks(&self) -> u16 {
+        1 << self.block_bits
+    }
+
+    // Number of bytes addressed through each slave address
+    pub(crate) fn block_size(&self) -> usize {
+        1 << (8 * self.pointer_width)
+    }
+
+    // The slave address and register pointer for the byte at offset, on the
+    // device whose first address is addr
+    pub(crate) fn locate(&self, addr: u16, offset: usize) -> (u16, RegPointer) {
+        let reg = RegPointer {
+            reg: (offset % self.block_size()) as u32,
+            width: self.pointer_width,
+            lsb_first: false,
+        };
+        (addr | (offset / self.block_size()) as u16, reg)
+    }
+}
+
+// How an EEPROM is programmed - the page size to write, the most bytes to
+// send in one message, and how long to wait for each write cycle to complete,
+// polling every interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Programming {
+    pub page: usize,
+    pub max_msg: usize,
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+// Reads values.len() bytes starting at offset, in messages of at most
+// max_msg bytes.  Failures are reported at the offset in the EEPROM.
+pub(crate) fn read(
+    bus: &mut Bus,
+    addr: u16,
+    geometry: &Geometry,
+    offset: usize,
+    values: &mut [u8],
+    max_msg: usize,
+) -> Result<(), ChunkError> {
+    let chunking = Chunking {
+        len: max_msg,
+        page: None,
+        fixed: false,
+    };
+    let mut done = 0;
+    while done < values.len() {
+        // A sequential read doesn't continue into the next block
+        let at = offset + done;
+        let len = min(values.len() - done, geometry.block_size() - at % geometry.block_size());
+        let (slave, reg) = geometry.locate(addr, at);
+        bus.read_reg_chunked(slave, reg, &mut values[done..done + len], chunking)
+            .map_err(|e| ChunkError {
+                offset: at + e.offset,
+                error: e.error,
+            })?;
+        done += len;
+    }
+    Ok(())
+}
+
+// Writes values starting at offset, a page (or as much of one as fits in a
+// message) at a time, waiting for the write cycle after each, and returns the
+// number of writes.  Failures are reported at the offset in the EEPROM.
+pub(crate) fn write(
+    bus: &mut Bus,
+    addr: u16,
+    geometry: &Geometry,
+    offset: usize,
+    values: &[u8],
+    programming: Programming,
+) -> Result<usize, ChunkError> {
+    let Programming { page, max_msg, timeout, interval } = programming;
+    let mut done = 0;
+    let mut writes = 0;
+    while done < values.len() {
+        let at = offset + done;
+        let len = min(min(values.len() - done, page - at % page), max_msg - geometry.pointer_width);
+        let (slave, reg) = geometry.locate(addr, at);
+        bus.write_reg_bytes(slave, reg, &values[done..done + len])
+            .and_then(|_| bus.poll_ack(slave, ProbeMode::Read, timeout, interval))
+            .map_err(|error| ChunkError { offset: at, error })?;
+        done += len;
+        writes += 1;
+    }
+    Ok(writes)
+}
+
+// Reads back values written at offset, failing with EIO at the first byte
+// which differs
+pub(crate) fn verify(
+    bus: &mut Bus,
+    addr: u16,
+    geometry: &Geometry,
+    offset: usize,
+    values: &[u8],
+    max_msg: usize,
+) -> Result<(), ChunkError> {
+    let mut read: Vec<u8> = vec![0; values.len()];
+    self::read(bus, addr, geometry, offset, &mut read, max_msg)?;
+    match read.iter().zip(values).position(|(read, value)| read != value) {
+        Some(i) => Err(ChunkError {
+            offset: offset + i,
+            error: BusError::from(std::io::Error::from_raw_os_error(nix::errno::Errno::EIO as i32)),
+        }),
+        None => Ok(()),
+    }
+}
+
+// An image to program, as runs of contiguous data, each with its offset
+pub(crate) type Image = Vec<(usize, Vec<u8>)>;
+
+// Parses an Intel HEX image, with its runs sorted by offset.  Data, end of
+// file, extended segment address and extended linear address records are
+// supported, and start address records ignored.
+pub(crate) fn parse_hex(text: &str) -> Result<Image, String> {
+    let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+    let mut base = 0;
+    for (i, line) in text.lines().map(str::trim).enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let bytes = match line.strip_prefix(':').map(parse_hex_bytes) {
+            Some(Some(bytes)) if bytes.len() >= 5 && bytes.len() == usize::from(bytes[0]) + 5 => bytes,
+            _ => return Err(format!("line {}: invalid record", i + 1)),
+        };
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(format!("line {}: bad checksum", i + 1));
+        }
+        let data = &bytes[4..bytes.len() - 1];
+        let word = || (usize::from(data[0]) << 8) | usize::from(data[1]);
+        match (bytes[3], data.len()) {
+            (0x00, _) => {
+                let offset = base + ((usize::from(bytes[1]) << 8) | usize::from(bytes[2]));
+                runs.push((offset, data.to_vec()));
+            }
+            (0x01, _) => break,
+            (0x02, 2) => base = word() << 4,
+            (0x04, 2) => base = word() << 16,
+            (0x03, _) | (0x05, _) => (),
+            (kind, _) => return Err(format!("line {}: unsupported record type {:02x}", i + 1, kind)),
+        }
+    }
+
+    // Merge adjacent records, so each run is written as few pages as possible
+    runs.sort_by_key(|(offset, _)| *offset);
+    let mut merged: Image = Vec::new();
+    for (offset, data) in runs {
+        match merged.last_mut() {
+            Some((last, last_data)) if *last + last_data.len() > offset => {
+                return Err(format!("data at {:#x} overlaps earlier data", offset))
+            }
+            Some((last, last_data)) if *last + last_data.len() == offset => last_data.extend(data),
+            _ => merged.push((offset, data)),
+        }
+    }
+    Ok(merged)
+}
+
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match std::str::from_utf8(pair) {
+            Ok(pair) if pair.len() == 2 => u8::from_str_radix(pair, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+// Formats data read from offset as Intel HEX, with up to 16 bytes per
+// record, and no record crossing a 64KiB boundary
+pub(crate) fn format_hex(offset: usize, data: &[u8]) -> String {
+    let mut text = String::new();
+    let mut upper = 0;
+    let mut done = 0;
+    while done < data.len() {
+        let at = offset + done;
+        if at >> 16 != upper {
+            upper = at >> 16;
+            hex_record(&mut text, 0, 0x04, &[(upper >> 8) as u8, upper as u8]);
+        }
+        let len = min(min(16, data.len() - done), 0x10000 - (at & 0xffff));
+        hex_record(&mut text, at as u16, 0x00, &data[done..done + len]);
+        done += len;
+    }
+    hex_record(&mut text, 0, 0x01, &[]);
+    text
+}
+
+fn hex_record(text: &mut String, addr: u16, kind: u8, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8, (addr >> 8) as u8, addr as u8, kind];
+    bytes.extend_from_slice(data);
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(sum.wrapping_neg());
+    text.push(':');
+    for byte in bytes {
+        write!(text, "{:02X}", byte).unwrap();
+    }
+    text.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::i2c::sim::{SimBus, SimDevice};
+    use super::super::i2c::smbus::{SmbusData, SmbusOp};
+    use super::super::i2c::{Backend, BusInfo, Msg};
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Writes sent to the bus, as (addr, data)
+    type Writes = Arc<Mutex<Vec<(u16, Vec<u8>)>>>;
+
+    // Records the writes made to a simulated bus
+    struct Recorder {
+        bus: SimBus,
+        writes: Writes,
+    }
+
+    impl Backend for Recorder {
+        fn rdwr(&mut self, msgs: &mut Vec<Msg>) -> Result<i32, BusError> {
+            for msg in msgs.iter().filter(|msg| !msg.is_read()) {
+                self.writes.lock().unwrap().push((msg.full_addr(), msg.data.clone()));
+            }
+            self.bus.rdwr(msgs)
+        }
+
+        fn smbus(&mut self, addr: u16, op: &SmbusOp) -> Result<SmbusData, BusError> {
+            self.bus.smbus(addr, op)
+        }
+
+        fn funcs(&mut self) -> Result<u64, BusError> {
+            self.bus.funcs()
+        }
+    }
+
+    // A bus with the EEPROM at 0x50, with each block a simulated device
+    fn eeprom_bus(geometry: &Geometry) -> (BusInfo, Writes) {
+        let mut bus = SimBus::new();
+        for block in 0..geometry.blocks() {
+            bus.add_device(0x50 + block, SimDevice::new(geometry.block_size()));
+        }
+        let writes: Writes = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            bus,
+            writes: writes.clone(),
+        };
+        let info = BusInfo::with_backend(9999, "sim-test".to_string(), None, Box::new(recorder));
+        (info, writes)
+    }
+
+    fn programming(geometry: &Geometry, max_msg: usize) -> Programming {
+        Programming {
+            page: geometry.page,
+            max_msg,
+            timeout: Duration::from_millis(10),
+            interval: Duration::from_millis(1),
+        }
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 1) as u8).collect()
+    }
+
+    // Checks no data write runs past the end of the page it starts in, as a
+    // real EEPROM would wrap it around within the page
+    fn assert_within_pages(geometry: &Geometry, writes: &Writes) {
+        let writes = writes.lock().unwrap();
+        let data_writes = writes.iter().filter(|(_, data)| data.len() > geometry.pointer_width);
+        for (addr, data) in data_writes {
+            let (pointer, values) = data.split_at(geometry.pointer_width);
+            let reg = pointer.iter().fold(0, |reg, byte| (reg << 8) | usize::from(*byte));
+            let offset = usize::from(addr - 0x50) * geometry.block_size() + reg;
+            assert!(
+                offset % geometry.page + values.len() <= geometry.page,
+                "write of {} bytes at {:#x} crosses a page",
+                values.len(),
+                offset
+            );
+        }
+    }
+
+    fn geometry_named(name: &str) -> &'static Geometry {
+        geometry(name).unwrap()
+    }
+
+    #[test]
+    fn geometries() {
+        let geometry = geometry_named("24c16");
+        assert_eq!(geometry.blocks(), 8);
+        assert_eq!(geometry.block_size(), 256);
+        let (addr, reg) = geometry.locate(0x50, 0x3ff);
+        assert_eq!((addr, reg.reg, reg.width), (0x53, 0xff, 1));
+        let geometry = geometry_named("24c1024");
+        let (addr, reg) = geometry.locate(0x50, 0x1_0010);
+        assert_eq!((addr, reg.reg, reg.width), (0x51, 0x10, 2));
+        assert!(super::geometry("24c03").is_none());
+        for geometry in GEOMETRIES {
+            assert!(geometry.blocks() as usize * geometry.block_size() >= geometry.size);
+            assert!(geometry.page.is_power_of_two());
+        }
+    }
+
+    #[test]
+    fn write_splits_at_page_boundaries() {
+        let geometry = geometry_named("24c02");
+        let (info, writes) = eeprom_bus(geometry);
+        let mut bus = info.lock();
+        let values = data(20);
+        // 0x05-0x07, 0x08-0x0f, 0x10-0x17 and 0x18
+        let count = write(&mut bus, 0x50, geometry, 5, &values, programming(geometry, 64)).unwrap();
+        assert_eq!(count, 4);
+        assert_within_pages(geometry, &writes);
+        let mut read_back: Vec<u8> = vec![0; values.len()];
+        read(&mut bus, 0x50, geometry, 5, &mut read_back, 64).unwrap();
+        assert_eq!(read_back, values);
+    }
+
+    #[test]
+    fn write_limited_by_message_size() {
+        let geometry = geometry_named("24c256");
+        let (info, writes) = eeprom_bus(geometry);
+        let mut bus = info.lock();
+        let values = data(100);
+        // Each message has a 2 byte pointer, leaving room for 8 bytes, so 4
+        // bytes to the end of the first page, then 12 writes of 8 bytes
+        let count = write(&mut bus, 0x50, geometry, 60, &values, programming(geometry, 10)).unwrap();
+        assert_eq!(count, 13);
+        assert_within_pages(geometry, &writes);
+        verify(&mut bus, 0x50, geometry, 60, &values, 10).unwrap();
+    }
+
+    #[test]
+    fn write_across_blocks() {
+        let geometry = geometry_named("24c04");
+        let (info, writes) = eeprom_bus(geometry);
+        let mut bus = info.lock();
+        let values = data(24);
+        write(&mut bus, 0x50, geometry, 0xf4, &values, programming(geometry, 64)).unwrap();
+        assert_within_pages(geometry, &writes);
+        assert!(writes.lock().unwrap().iter().any(|(addr, data)| *addr == 0x51 && data[0] == 0));
+        let mut read_back: Vec<u8> = vec![0; values.len()];
+        read(&mut bus, 0x50, geometry, 0xf4, &mut read_back, 16).unwrap();
+        assert_eq!(read_back, values);
+    }
+
+    #[test]
+    fn verify_reports_first_mismatch() {
+        let geometry = geometry_named("24c02");
+        let (info, _) = eeprom_bus(geometry);
+        let mut bus = info.lock();
+        let values = data(16);
+        write(&mut bus, 0x50, geometry, 0, &values, programming(geometry, 64)).unwrap();
+        let mut other = values.clone();
+        other[9] ^= 0xff;
+        let e = verify(&mut bus, 0x50, geometry, 0, &other, 64).unwrap_err();
+        assert_eq!(e.offset, 9);
+    }
+
+    #[test]
+    fn write_to_missing_eeprom_fails_at_offset() {
+        let geometry = geometry_named("24c02");
+        let (info, _) = eeprom_bus(geometry_named("24c01"));
+        let mut bus = info.lock();
+        let e = write(&mut bus, 0x51, geometry, 8, &data(4), programming(geometry, 64)).unwrap_err();
+        assert_eq!(e.offset, 8);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        // Crosses a 64KiB boundary, so needs an extended linear address
+        let values = data(40);
+        let text = format_hex(0xfff0, &values);
+        assert!(text.contains(":020000040001F9\n"));
+        assert!(text.ends_with(":00000001FF\n"));
+        assert_eq!(parse_hex(&text).unwrap(), vec![(0xfff0, values)]);
+    }
+
+    #[test]
+    fn hex_merges_and_sorts_records() {
+        let text = ":020010000304E7\n:0400000001020304F2\n:020004000506EF\n:00000001FF\n";
+        let image = parse_hex(text).unwrap();
+        assert_eq!(image, vec![(0, vec![1, 2, 3, 4, 5, 6]), (0x10, vec![3, 4])]);
+    }
+
+    #[test]
+    fn hex_errors() {
+        assert!(parse_hex(":0400000001020304F3\n").unwrap_err().contains("bad checksum"));
+        assert!(parse_hex("0400000001020304F2\n").unwrap_err().contains("invalid record"));
+        assert!(parse_hex(":00000006FA\n").unwrap_err().contains("unsupported record type"));
+        let overlapping = ":0400000001020304F2\n:020002000506F1\n";
+        assert!(parse_hex(overlapping).unwrap_err().contains("overlaps"));
+    }
+}
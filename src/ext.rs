@@ -0,0 +1,1458 @@
+//! Extensions to the i2cbus_api server.
+//!
+//! Wraps i2cbus_api::server::Service, serving routes (and extended versions
+//! of existing routes) which the generated server doesn't yet provide, and
+//! passing everything else through to it.
+
+use futures::{future, Future, Stream};
+use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
+use i2cbus_api::Api;
+use log::{info, warn};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use swagger::auth::Authorization;
+use swagger::context::ContextualPayload;
+use swagger::{Has, XSpanIdString};
+use tokio::timer::Timeout;
+
+use super::auth;
+use super::http;
+use super::profile;
+
+#[path = "ext_models.rs"]
+pub(crate) mod models;
+
+// Responses
+
+/// Implemented by each response type served by ext, to turn it into an HTTP
+/// status code and JSON body
+pub(crate) trait ExtResponse {
+    fn status(&self) -> StatusCode;
+    fn body(&self) -> Vec<u8>;
+
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+}
+
+// Implements ExtResponse for a response enum, given the status code for each
+// variant.  Every variant must hold a serializable body.
+macro_rules! impl_ext_response {
+    ($type:ident, $($variant:ident => $status:expr),+) => {
+        impl ExtResponse for $type {
+            fn status(&self) -> StatusCode {
+                match self {
+                    $($type::$variant(_) => StatusCode::from_u16($status)
+                        .expect("Invalid status code"),)+
+                }
+            }
+
+            fn body(&self) -> Vec<u8> {
+                match self {
+                    $($type::$variant(body) => serde_json::to_vec(body)
+                        .expect("impossible to fail to serialize"),)+
+                }
+            }
+        }
+    };
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum I2cBusListResponse {
+    /// OK
+    OK(Vec<models::I2cBusList>),
+}
+impl_ext_response!(I2cBusListResponse, OK => 200);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusRescanResponse {
+    /// OK
+    OK(Vec<models::I2cBusList>),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusRescanResponse, OK => 200, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusInfoResponse {
+    /// OK
+    OK(models::I2cBusList),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+}
+impl_ext_response!(I2cBusInfoResponse, OK => 200, BadRequest => 400);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusScanResponse {
+    /// OK
+    OK(models::I2cBusScan),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusScanResponse, OK => 200, BadRequest => 400, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusDumpResponse {
+    /// OK
+    OK(models::I2cBusDump),
+    /// OK, rendered as text
+    OKText(String),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusTransactionResponse {
+    /// OK
+    OK(models::I2cBusTransactionResult),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusTransactionResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusSmbusResponse {
+    /// OK
+    OK(models::I2cBusSmbusResult),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusSmbusResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+// Responses for the routes i2cbus_api's generated service also serves.  ext
+// serves these itself, so it can return a 403 if the policy forbids the
+// operation, which the generated responses have no variant for.
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusReadByteResponse {
+    /// OK
+    OK(i2cbus_api::models::I2cBusRead),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusReadByteResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusReadBytesResponse {
+    /// OK
+    OK(i2cbus_api::models::I2cBusRead),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusReadBytesResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusReadRegResponse {
+    /// OK
+    OK(i2cbus_api::models::I2cBusRead),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusReadRegResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusWriteByteResponse {
+    /// OK
+    OK(i2cbus_api::models::I2cBusOk),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusWriteByteResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusWriteByteRegResponse {
+    /// OK
+    OK(i2cbus_api::models::I2cBusOk),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusWriteByteRegResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusWriteBytesResponse {
+    /// OK
+    OK(i2cbus_api::models::I2cBusOk),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusWriteBytesResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusWriteBytesRegResponse {
+    /// OK
+    OK(i2cbus_api::models::I2cBusOk),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusWriteBytesRegResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+// Converts one of the above to the corresponding generated response, for the
+// generated service's Api implementation.  A 403 can only be returned as an
+// error.
+macro_rules! impl_api_response_from {
+    ($type:ident) => {
+        impl From<$type> for Result<i2cbus_api::$type, i2cbus_api::ApiError> {
+            fn from(rsp: $type) -> Self {
+                match rsp {
+                    $type::OK(body) => Ok(i2cbus_api::$type::OK(body)),
+                    $type::BadRequest(body) => Ok(i2cbus_api::$type::BadRequest(body)),
+                    $type::TransactionFailed(body) => Ok(i2cbus_api::$type::TransactionFailed(body)),
+                    $type::Forbidden(body) => Err(i2cbus_api::ApiError(format!("{:?}", body))),
+                }
+            }
+        }
+    };
+}
+
+impl_api_response_from!(I2cBusReadByteResponse);
+impl_api_response_from!(I2cBusReadBytesResponse);
+impl_api_response_from!(I2cBusReadRegResponse);
+impl_api_response_from!(I2cBusWriteByteResponse);
+impl_api_response_from!(I2cBusWriteByteRegResponse);
+impl_api_response_from!(I2cBusWriteBytesResponse);
+impl_api_response_from!(I2cBusWriteBytesRegResponse);
+
+impl ExtResponse for I2cBusDumpResponse {
+    fn status(&self) -> StatusCode {
+        match self {
+            I2cBusDumpResponse::OK(_) | I2cBusDumpResponse::OKText(_) => StatusCode::OK,
+            I2cBusDumpResponse::BadRequest(_) => StatusCode::BAD_REQUEST,
+            I2cBusDumpResponse::TransactionFailed(_) => StatusCode::BAD_GATEWAY,
+            I2cBusDumpResponse::Forbidden(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn body(&self) -> Vec<u8> {
+        let body = match self {
+            I2cBusDumpResponse::OK(body) => serde_json::to_vec(body),
+            I2cBusDumpResponse::OKText(body) => return body.clone().into_bytes(),
+            I2cBusDumpResponse::BadRequest(body) => serde_json::to_vec(body),
+            I2cBusDumpResponse::TransactionFailed(body) => serde_json::to_vec(body),
+            I2cBusDumpResponse::Forbidden(body) => serde_json::to_vec(body),
+        };
+        body.expect("impossible to fail to serialize")
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            I2cBusDumpResponse::OKText(_) => "text/plain",
+            _ => "application/json",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusModifyResponse {
+    /// OK
+    OK(models::I2cBusModifyResult),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusModifyResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusValueResponse {
+    /// OK
+    OK(models::I2cBusValue),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusValueResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusWaitResponse {
+    /// OK
+    OK(models::I2cBusWaitResult),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusWaitResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusChunkedResponse {
+    /// OK
+    OK(models::I2cBusChunked),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(models::I2cBusChunkedError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusChunkedResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum I2cBusEepromTypesResponse {
+    /// OK
+    OK(Vec<models::I2cBusEepromType>),
+}
+impl_ext_response!(I2cBusEepromTypesResponse, OK => 200);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusEepromReadResponse {
+    /// OK, as a binary image
+    OK(Vec<u8>),
+    /// OK, as Intel HEX
+    OKText(String),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(models::I2cBusChunkedError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+
+impl ExtResponse for I2cBusEepromReadResponse {
+    fn status(&self) -> StatusCode {
+        match self {
+            I2cBusEepromReadResponse::OK(_) | I2cBusEepromReadResponse::OKText(_) => StatusCode::OK,
+            I2cBusEepromReadResponse::BadRequest(_) => StatusCode::BAD_REQUEST,
+            I2cBusEepromReadResponse::TransactionFailed(_) => StatusCode::BAD_GATEWAY,
+            I2cBusEepromReadResponse::Forbidden(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn body(&self) -> Vec<u8> {
+        let body = match self {
+            I2cBusEepromReadResponse::OK(body) => return body.clone(),
+            I2cBusEepromReadResponse::OKText(body) => return body.clone().into_bytes(),
+            I2cBusEepromReadResponse::BadRequest(body) => serde_json::to_vec(body),
+            I2cBusEepromReadResponse::TransactionFailed(body) => serde_json::to_vec(body),
+            I2cBusEepromReadResponse::Forbidden(body) => serde_json::to_vec(body),
+        };
+        body.expect("impossible to fail to serialize")
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            I2cBusEepromReadResponse::OK(_) => "application/octet-stream",
+            I2cBusEepromReadResponse::OKText(_) => "text/plain",
+            _ => "application/json",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusEepromWriteResponse {
+    /// OK
+    OK(models::I2cBusEepromWritten),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(models::I2cBusChunkedError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusEepromWriteResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum I2cBusProfilesResponse {
+    /// OK
+    OK(Vec<profile::Profile>),
+}
+impl_ext_response!(I2cBusProfilesResponse, OK => 200);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusDeviceResponse {
+    /// OK
+    OK(models::I2cBusDevice),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusDeviceResponse, OK => 200, BadRequest => 400, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+#[must_use]
+pub(crate) enum I2cBusNamedRegResponse {
+    /// OK
+    OK(models::I2cBusNamedReg),
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+    /// Transaction Failed
+    TransactionFailed(i2cbus_api::models::I2cBusError),
+    /// Forbidden
+    Forbidden(models::I2cBusForbidden),
+}
+impl_ext_response!(I2cBusNamedRegResponse, OK => 200, BadRequest => 400, TransactionFailed => 502, Forbidden => 403);
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum HealthResponse {
+    /// OK
+    OK(models::I2cBusHealth),
+}
+impl_ext_response!(HealthResponse, OK => 200);
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum ReadyResponse {
+    /// OK
+    OK(models::I2cBusReady),
+    /// Service Unavailable
+    ServiceUnavailable(models::I2cBusReady),
+}
+impl_ext_response!(ReadyResponse, OK => 200, ServiceUnavailable => 503);
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum MetricsResponse {
+    /// OK, in the Prometheus text format
+    OK(String),
+}
+
+impl ExtResponse for MetricsResponse {
+    fn status(&self) -> StatusCode {
+        StatusCode::OK
+    }
+
+    fn body(&self) -> Vec<u8> {
+        match self {
+            MetricsResponse::OK(body) => body.clone().into_bytes(),
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/plain; version=0.0.4"
+    }
+}
+
+// Routes
+
+mod paths {
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        pub static ref REGEX_HEALTH: regex::Regex =
+            regex::Regex::new(r"^/health$")
+                .expect("Unable to create regex for HEALTH");
+        pub static ref REGEX_READY: regex::Regex =
+            regex::Regex::new(r"^/ready$")
+                .expect("Unable to create regex for READY");
+        pub static ref REGEX_METRICS: regex::Regex =
+            regex::Regex::new(r"^/metrics$")
+                .expect("Unable to create regex for METRICS");
+        pub static ref REGEX_I2C_BUSLIST: regex::Regex =
+            regex::Regex::new(r"^/i2c/buslist$")
+                .expect("Unable to create regex for I2C_BUSLIST");
+        pub static ref REGEX_I2C_BUS_RESCAN: regex::Regex =
+            regex::Regex::new(r"^/i2c/bus/rescan$")
+                .expect("Unable to create regex for I2C_BUS_RESCAN");
+        pub static ref REGEX_I2C_BUSID_INFO: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/info$")
+                .expect("Unable to create regex for I2C_BUSID_INFO");
+        pub static ref REGEX_I2C_BUS_BUSID_SCAN: regex::Regex =
+            regex::Regex::new(r"^/i2c/bus/(?P<busId>[^/?#]*)/scan$")
+                .expect("Unable to create regex for I2C_BUS_BUSID_SCAN");
+        pub static ref REGEX_I2C_BUSID_DUMP_ADDR: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/dump/(?P<addr>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_DUMP_ADDR");
+        pub static ref REGEX_I2C_BUSID_TRANSACTION: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/transaction$")
+                .expect("Unable to create regex for I2C_BUSID_TRANSACTION");
+        pub static ref REGEX_I2C_BUSID_SMBUS_ADDR: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/smbus/(?P<addr>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_SMBUS_ADDR");
+        pub static ref REGEX_I2C_BUSID_MODIFY_ADDR_REG: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/modify/(?P<addr>[^/?#]*)/(?P<reg>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_MODIFY_ADDR_REG");
+        pub static ref REGEX_I2C_BUSID_READ_VALUE_ADDR_REG: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/read/value/(?P<addr>[^/?#]*)/(?P<reg>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_READ_VALUE_ADDR_REG");
+        pub static ref REGEX_I2C_BUSID_WRITE_VALUE_ADDR_REG: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/write/value/(?P<addr>[^/?#]*)/(?P<reg>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_WRITE_VALUE_ADDR_REG");
+        pub static ref REGEX_I2C_BUSID_READ_CHUNKED_ADDR_REG_NUMBYTES: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/read/chunked/(?P<addr>[^/?#]*)/(?P<reg>[^/?#]*)/(?P<numBytes>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_READ_CHUNKED_ADDR_REG_NUMBYTES");
+        pub static ref REGEX_I2C_BUSID_WRITE_CHUNKED_ADDR_REG: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/write/chunked/(?P<addr>[^/?#]*)/(?P<reg>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_WRITE_CHUNKED_ADDR_REG");
+        pub static ref REGEX_I2C_BUSID_WAIT_ADDR: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/wait/(?P<addr>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_WAIT_ADDR");
+        pub static ref REGEX_I2C_EEPROM_TYPES: regex::Regex =
+            regex::Regex::new(r"^/i2c/eeprom/types$")
+                .expect("Unable to create regex for I2C_EEPROM_TYPES");
+        pub static ref REGEX_I2C_BUSID_EEPROM_ADDR: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/eeprom/(?P<addr>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_EEPROM_ADDR");
+        pub static ref REGEX_I2C_BUSID_READ_BYTE_ADDR: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/read/byte/(?P<addr>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_READ_BYTE_ADDR");
+        pub static ref REGEX_I2C_BUSID_READ_BYTES_ADDR_NUMBYTES: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/read/bytes/(?P<addr>[^/?#]*)/(?P<numBytes>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_READ_BYTES_ADDR_NUMBYTES");
+        pub static ref REGEX_I2C_BUSID_READ_REG_ADDR_REG_NUMBYTES: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/read/reg/(?P<addr>[^/?#]*)/(?P<reg>[^/?#]*)/(?P<numBytes>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_READ_REG_ADDR_REG_NUMBYTES");
+        pub static ref REGEX_I2C_BUSID_WRITE_BYTE_REG_ADDR_REG_VALUE: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/write/byte/reg/(?P<addr>[^/?#]*)/(?P<reg>[^/?#]*)/(?P<value>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_WRITE_BYTE_REG_ADDR_REG_VALUE");
+        pub static ref REGEX_I2C_BUSID_WRITE_BYTE_ADDR_VALUE: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/write/byte/(?P<addr>[^/?#]*)/(?P<value>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_WRITE_BYTE_ADDR_VALUE");
+        pub static ref REGEX_I2C_BUSID_WRITE_BYTES_REG_ADDR_REG: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/write/bytes/reg/(?P<addr>[^/?#]*)/(?P<reg>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_WRITE_BYTES_REG_ADDR_REG");
+        pub static ref REGEX_I2C_BUSID_WRITE_BYTES_ADDR: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/write/bytes/(?P<addr>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUSID_WRITE_BYTES_ADDR");
+        pub static ref REGEX_I2C_PROFILES: regex::Regex =
+            regex::Regex::new(r"^/i2c/profiles$")
+                .expect("Unable to create regex for I2C_PROFILES");
+        pub static ref REGEX_I2C_BUS_BUSID_DEVICE_ADDR: regex::Regex =
+            regex::Regex::new(r"^/i2c/bus/(?P<busId>[^/?#]*)/device/(?P<addr>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUS_BUSID_DEVICE_ADDR");
+        pub static ref REGEX_I2C_BUS_BUSID_DEVICE_ADDR_REG_NAME: regex::Regex =
+            regex::Regex::new(r"^/i2c/bus/(?P<busId>[^/?#]*)/device/(?P<addr>[^/?#]*)/reg/(?P<name>[^/?#]*)$")
+                .expect("Unable to create regex for I2C_BUS_BUSID_DEVICE_ADDR_REG_NAME");
+        pub static ref REGEX_I2C_BUS_BUSID: regex::Regex =
+            regex::Regex::new(r"^/i2c/bus/(?P<busId>[^/?#]*)/(?P<rest>scan|device/.*)$")
+                .expect("Unable to create regex for I2C_BUS_BUSID");
+        pub static ref REGEX_I2C_BUSID: regex::Regex =
+            regex::Regex::new(r"^/i2c/(?P<busId>[^/?#]*)/(?P<rest>.*)$")
+                .expect("Unable to create regex for I2C_BUSID");
+    }
+}
+
+type ServiceFuture = Box<dyn Future<Item = Response<Body>, Error = hyper::Error> + Send>;
+
+fn response<R>(rsp: R, span_id: &XSpanIdString) -> Response<Body>
+where
+    R: ExtResponse,
+{
+    let mut response = Response::new(Body::from(rsp.body()));
+    *response.status_mut() = rsp.status();
+    response.headers_mut().insert(
+        HeaderName::from_static("x-span-id"),
+        HeaderValue::from_str(span_id.0.to_string().as_str())
+            .expect("Unable to create X-Span-ID header value"),
+    );
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(rsp.content_type()),
+    );
+    response
+}
+
+fn respond<R, C>(rsp: R, context: &C) -> ServiceFuture
+where
+    R: ExtResponse,
+    C: Has<XSpanIdString>,
+{
+    Box::new(future::ok(response(rsp, context.get())))
+}
+
+// Runs the handler, which carries out bus operations, on the blocking pool,
+// and responds with its result
+fn respond_blocking<R, C, F>(handler: F, context: &C) -> ServiceFuture
+where
+    R: ExtResponse + Send + 'static,
+    C: Has<XSpanIdString>,
+    F: FnOnce() -> R + Send + 'static,
+{
+    let span_id = context.get().clone();
+    Box::new(http::blocking(handler).map(move |rsp| response(rsp, &span_id)))
+}
+
+// Fails the request with a 504 if it isn't complete within the timeout
+fn with_timeout(rsp: ServiceFuture, span_id: XSpanIdString) -> ServiceFuture {
+    let timeout = http::timeout();
+    Box::new(Timeout::new(rsp, timeout).or_else(move |e| {
+        if e.is_inner() {
+            return Err(e.into_inner().unwrap());
+        }
+        if e.is_timer() {
+            warn!("Timer error {:?}", e.into_timer());
+        }
+        let rsp = TimeoutResponse::GatewayTimeout(i2cbus_api::models::I2cBusError {
+            error: Some(nix::errno::Errno::ETIMEDOUT as i32),
+            description: Some(format!("Request timed out after {}ms", timeout.as_millis())),
+        });
+        info!("API {} -> {:?}", "with_timeout", rsp);
+        Ok(response(rsp, &span_id))
+    }))
+}
+
+#[derive(Debug, PartialEq)]
+enum TimeoutResponse {
+    /// Gateway Timeout
+    GatewayTimeout(i2cbus_api::models::I2cBusError),
+}
+impl_ext_response!(TimeoutResponse, GatewayTimeout => 504);
+
+fn bad_request(description: String) -> ServiceFuture {
+    Box::new(future::ok(
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(description))
+            .expect("Unable to create Bad Request response"),
+    ))
+}
+
+// Returns the named path parameter, from a path already matched against the
+// regex
+fn path_param<T>(regex: &Regex, path: &str, name: &str) -> Result<T, ServiceFuture>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let caps = regex.captures(path).unwrap_or_else(|| {
+        panic!("Path {} failed match against \"{}\"", path, regex.as_str())
+    });
+    match percent_encoding::percent_decode(caps[name].as_bytes()).decode_utf8() {
+        Ok(param) => param.parse::<T>().map_err(|e| {
+            bad_request(format!("Couldn't parse path parameter {}: {}", name, e))
+        }),
+        Err(_) => Err(bad_request(format!(
+            "Couldn't percent-decode path parameter as UTF-8: {}",
+            &caps[name]
+        ))),
+    }
+}
+
+fn query_params<T>(uri: &Uri) -> Result<T, ServiceFuture>
+where
+    T: DeserializeOwned,
+{
+    serde_urlencoded::from_str::<T>(uri.query().unwrap_or(""))
+        .map_err(|e| bad_request(format!("Couldn't parse query parameters: {}", e)))
+}
+
+// Passes the raw request body, along with the request context, to the handler
+fn raw_body<C, F>(req: Request<ContextualPayload<Body, C>>, handler: F) -> ServiceFuture
+where
+    C: Has<XSpanIdString> + 'static + Send,
+    F: FnOnce(Vec<u8>, &C) -> ServiceFuture + Send + 'static,
+{
+    let body = req.into_body();
+    let context = body.context;
+    Box::new(body.inner.concat2().and_then(move |body| handler(body.to_vec(), &context)))
+}
+
+// Parses the request body as JSON, and passes it, along with the request
+// context, to the handler
+fn json_body<T, C, F>(req: Request<ContextualPayload<Body, C>>, handler: F) -> ServiceFuture
+where
+    T: DeserializeOwned,
+    C: Has<XSpanIdString> + 'static + Send,
+    F: FnOnce(T, &C) -> ServiceFuture + Send + 'static,
+{
+    let body = req.into_body();
+    let context = body.context;
+    Box::new(body.inner.concat2().and_then(move |body| {
+        match serde_json::from_slice::<T>(&body) {
+            Ok(param) => handler(param, &context),
+            Err(e) => bad_request(format!("Couldn't parse body parameter: {}", e)),
+        }
+    }))
+}
+
+// Buses may be addressed by sysfs adapter name (percent-encoded) as well as
+// by ID.  As the generated server only understands IDs, rewrite any name in
+// the path to the corresponding ID.  Scan and device routes, under
+// /i2c/bus/, are rewritten likewise.
+fn resolve_bus_name<C>(req: &mut Request<ContextualPayload<Body, C>>) -> Result<(), ServiceFuture>
+where
+    C: Has<XSpanIdString> + 'static + Send,
+{
+    let path = req.uri().path();
+    if paths::REGEX_I2C_BUS_RESCAN.is_match(path) || paths::REGEX_I2C_EEPROM_TYPES.is_match(path) {
+        return Ok(());
+    }
+    let path = req.uri().path();
+    let (prefix, caps) = match paths::REGEX_I2C_BUS_BUSID.captures(path) {
+        Some(caps) => ("/i2c/bus/", Some(caps)),
+        None => ("/i2c/", paths::REGEX_I2C_BUSID.captures(path)),
+    };
+    let (name, rest) = match caps {
+        Some(caps) => (caps["busId"].to_string(), caps["rest"].to_string()),
+        None => return Ok(()),
+    };
+    if name.parse::<i32>().is_ok() {
+        return Ok(());
+    }
+    let name = percent_encoding::percent_decode(name.as_bytes())
+        .decode_utf8_lossy()
+        .to_string();
+    let bus_id = match http::bus_id_by_name(&name) {
+        Ok(bus_id) => bus_id,
+        Err(e) => {
+            let rsp = BusNameResponse::BadRequest(e);
+            info!("API {} -> {:?}", "resolve_bus_name", rsp);
+            return Err(respond(rsp, &req.body().context));
+        }
+    };
+    let query = match req.uri().query() {
+        Some(query) => format!("?{}", query),
+        None => String::new(),
+    };
+    let uri = format!("{}{}/{}{}", prefix, bus_id, rest, query);
+    match uri.parse::<Uri>() {
+        Ok(uri) => *req.uri_mut() = uri,
+        Err(e) => warn!("Failed to rewrite bus name {} in {} {}", name, req.uri(), e),
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum BusNameResponse {
+    /// Bad Request
+    BadRequest(i2cbus_api::models::I2cBusArg),
+}
+impl_ext_response!(BusNameResponse, BadRequest => 400);
+
+// Services
+
+pub struct MakeService<T, C> {
+    api_impl: T,
+    marker: PhantomData<C>,
+}
+
+impl<T, C> MakeService<T, C>
+where
+    T: Api<C> + Clone + Send + 'static,
+    C: Has<XSpanIdString> + 'static,
+{
+    pub fn new(api_impl: T) -> Self {
+        MakeService {
+            api_impl,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, SC, C> hyper::service::MakeService<&'a SC> for MakeService<T, C>
+where
+    T: Api<C> + Clone + Send + 'static,
+    C: Has<XSpanIdString> + Has<Option<Authorization>> + 'static + Send,
+{
+    type ReqBody = ContextualPayload<Body, C>;
+    type ResBody = Body;
+    type Error = hyper::Error;
+    type Service = Service<T, C>;
+    type Future = future::FutureResult<Self::Service, Self::MakeError>;
+    type MakeError = hyper::Error;
+
+    fn make_service(&mut self, _ctx: &'a SC) -> Self::Future {
+        future::ok(Service {
+            inner: i2cbus_api::server::Service::new(self.api_impl.clone()),
+        })
+    }
+}
+
+pub struct Service<T, C> {
+    inner: i2cbus_api::server::Service<T, C>,
+}
+
+impl<T, C> hyper::service::Service for Service<T, C>
+where
+    T: Api<C> + Clone + Send + 'static,
+    C: Has<XSpanIdString> + Has<Option<Authorization>> + 'static + Send,
+{
+    type ReqBody = ContextualPayload<Body, C>;
+    type ResBody = Body;
+    type Error = hyper::Error;
+    type Future = ServiceFuture;
+
+    fn call(&mut self, mut req: Request<Self::ReqBody>) -> Self::Future {
+        if let Err(rsp) = resolve_bus_name(&mut req) {
+            return rsp;
+        }
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        let context = &req.body().context;
+        let span_id = Has::<XSpanIdString>::get(context).clone();
+        let identity = auth::identity(context);
+        info!("{} {} from {} [{}]", method, path, identity, span_id.0);
+
+        let rsp = match method {
+            // I2cBusModify - POST /i2c/{busId}/modify/{addr}/{reg}
+            Method::POST if paths::REGEX_I2C_BUSID_MODIFY_ADDR_REG.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_MODIFY_ADDR_REG;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let reg: i32 = match path_param(re, &path, "reg") {
+                    Ok(reg) => reg,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                json_body(req, move |modify, context| {
+                    respond_blocking(
+                        move || http::modify_reg(&identity, &bus_id.into(), &addr.into(), &reg.into(), &modify, &args),
+                        context,
+                    )
+                })
+            }
+
+            // I2cBusReadValue - GET /i2c/{busId}/read/value/{addr}/{reg}
+            Method::GET if paths::REGEX_I2C_BUSID_READ_VALUE_ADDR_REG.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_READ_VALUE_ADDR_REG;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let reg: i32 = match path_param(re, &path, "reg") {
+                    Ok(reg) => reg,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(
+                    move || http::read_value(&identity, &bus_id.into(), &addr.into(), &reg.into(), &args),
+                    context,
+                )
+            }
+
+            // I2cBusWriteValue - POST /i2c/{busId}/write/value/{addr}/{reg}
+            Method::POST if paths::REGEX_I2C_BUSID_WRITE_VALUE_ADDR_REG.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_WRITE_VALUE_ADDR_REG;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let reg: i32 = match path_param(re, &path, "reg") {
+                    Ok(reg) => reg,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                json_body(req, move |write, context| {
+                    respond_blocking(
+                        move || http::write_value(&identity, &bus_id.into(), &addr.into(), &reg.into(), &write, &args),
+                        context,
+                    )
+                })
+            }
+
+            // I2cBusReadChunked - GET /i2c/{busId}/read/chunked/{addr}/{reg}/{numBytes}
+            Method::GET if paths::REGEX_I2C_BUSID_READ_CHUNKED_ADDR_REG_NUMBYTES.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_READ_CHUNKED_ADDR_REG_NUMBYTES;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let reg: i32 = match path_param(re, &path, "reg") {
+                    Ok(reg) => reg,
+                    Err(rsp) => return rsp,
+                };
+                let num_bytes: i32 = match path_param(re, &path, "numBytes") {
+                    Ok(num_bytes) => num_bytes,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(
+                    move || http::read_chunked(&identity, &bus_id.into(), &addr.into(), &reg.into(), num_bytes, &args),
+                    context,
+                )
+            }
+
+            // I2cBusWriteChunked - POST /i2c/{busId}/write/chunked/{addr}/{reg}
+            Method::POST if paths::REGEX_I2C_BUSID_WRITE_CHUNKED_ADDR_REG.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_WRITE_CHUNKED_ADDR_REG;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let reg: i32 = match path_param(re, &path, "reg") {
+                    Ok(reg) => reg,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                json_body(req, move |values, context| {
+                    respond_blocking(
+                        move || {
+                            http::write_chunked(&identity, &bus_id.into(), &addr.into(), &reg.into(), &values, &args)
+                        },
+                        context,
+                    )
+                })
+            }
+
+            // I2cBusWait - POST /i2c/{busId}/wait/{addr}
+            Method::POST if paths::REGEX_I2C_BUSID_WAIT_ADDR.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_WAIT_ADDR;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(move || http::wait(&identity, &bus_id.into(), &addr.into(), &args), context)
+            }
+
+            // I2cBusEepromTypes - GET /i2c/eeprom/types
+            Method::GET if paths::REGEX_I2C_EEPROM_TYPES.is_match(&path) => {
+                respond(http::eeprom_types(), context)
+            }
+
+            // I2cBusReadEeprom - GET /i2c/{busId}/eeprom/{addr}
+            Method::GET if paths::REGEX_I2C_BUSID_EEPROM_ADDR.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_EEPROM_ADDR;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(move || http::read_eeprom(&identity, &bus_id.into(), &addr.into(), &args), context)
+            }
+
+            // I2cBusWriteEeprom - PUT /i2c/{busId}/eeprom/{addr}
+            Method::PUT if paths::REGEX_I2C_BUSID_EEPROM_ADDR.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_EEPROM_ADDR;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                raw_body(req, move |image, context| {
+                    respond_blocking(
+                        move || http::write_eeprom(&identity, &bus_id.into(), &addr.into(), &image, &args),
+                        context,
+                    )
+                })
+            }
+
+            // I2cBusProfiles - GET /i2c/profiles
+            Method::GET if paths::REGEX_I2C_PROFILES.is_match(&path) => {
+                respond(http::get_profiles(), context)
+            }
+
+            // I2cBusDevice - GET, PUT and DELETE /i2c/bus/{busId}/device/{addr},
+            // to get, bind and unbind the device's profile
+            Method::GET | Method::PUT | Method::DELETE
+                if paths::REGEX_I2C_BUS_BUSID_DEVICE_ADDR.is_match(&path) =>
+            {
+                let re = &paths::REGEX_I2C_BUS_BUSID_DEVICE_ADDR;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                match method {
+                    Method::GET => respond(http::get_device(&identity, &bus_id.into(), &addr.into()), context),
+                    Method::PUT => json_body(req, move |device, context| {
+                        respond(http::bind_device(&identity, &bus_id.into(), &addr.into(), &device), context)
+                    }),
+                    _ => respond(http::unbind_device(&identity, &bus_id.into(), &addr.into()), context),
+                }
+            }
+
+            // I2cBusNamedReg - GET and PUT /i2c/bus/{busId}/device/{addr}/reg/{name}
+            Method::GET | Method::PUT
+                if paths::REGEX_I2C_BUS_BUSID_DEVICE_ADDR_REG_NAME.is_match(&path) =>
+            {
+                let re = &paths::REGEX_I2C_BUS_BUSID_DEVICE_ADDR_REG_NAME;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let name: String = match path_param(re, &path, "name") {
+                    Ok(name) => name,
+                    Err(rsp) => return rsp,
+                };
+                match method {
+                    Method::GET => respond_blocking(
+                        move || http::read_named_reg(&identity, &bus_id.into(), &addr.into(), &name),
+                        context,
+                    ),
+                    _ => json_body(req, move |write, context| {
+                        respond_blocking(
+                            move || {
+                                http::write_named_reg(&identity, &bus_id.into(), &addr.into(), &name, &write)
+                            },
+                            context,
+                        )
+                    }),
+                }
+            }
+
+            // Health - GET /health, for liveness probes
+            Method::GET if paths::REGEX_HEALTH.is_match(&path) => {
+                respond(http::health(), context)
+            }
+
+            // Ready - GET /ready, for readiness probes.  Probing required
+            // devices needs a pool thread, so isn't attempted if they're all
+            // busy.
+            Method::GET if paths::REGEX_READY.is_match(&path) => {
+                let pool = http::pool_status();
+                match pool.saturated() {
+                    true => respond(http::ready(pool), context),
+                    false => respond_blocking(move || http::ready(pool), context),
+                }
+            }
+
+            // Metrics - GET /metrics, in the Prometheus text format
+            Method::GET if paths::REGEX_METRICS.is_match(&path) => {
+                respond(http::metrics(), context)
+            }
+
+            // I2cBusList - GET /i2c/buslist, extended with adapter names
+            Method::GET if paths::REGEX_I2C_BUSLIST.is_match(&path) => {
+                respond(http::get_buses(), context)
+            }
+
+            // I2cBusRescan - POST /i2c/bus/rescan
+            Method::POST if paths::REGEX_I2C_BUS_RESCAN.is_match(&path) => {
+                let identity = auth::identity(context);
+                respond_blocking(move || http::rescan(&identity), context)
+            }
+
+            // I2cBusInfo - GET /i2c/{busId}/info
+            Method::GET if paths::REGEX_I2C_BUSID_INFO.is_match(&path) => {
+                let bus_id: i32 = match path_param(&paths::REGEX_I2C_BUSID_INFO, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                respond(http::get_bus_info(&bus_id.into()), context)
+            }
+
+            // I2cBusScan - GET /i2c/bus/{busId}/scan
+            Method::GET if paths::REGEX_I2C_BUS_BUSID_SCAN.is_match(&path) => {
+                let bus_id: i32 = match path_param(&paths::REGEX_I2C_BUS_BUSID_SCAN, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(move || http::scan(&identity, &bus_id.into(), &args), context)
+            }
+
+            // I2cBusDump - GET /i2c/{busId}/dump/{addr}
+            Method::GET if paths::REGEX_I2C_BUSID_DUMP_ADDR.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_DUMP_ADDR;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(move || http::dump(&identity, &bus_id.into(), &addr.into(), &args), context)
+            }
+
+            // I2cBusTransaction - POST /i2c/{busId}/transaction
+            Method::POST if paths::REGEX_I2C_BUSID_TRANSACTION.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_TRANSACTION;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                json_body(req, move |txn, context| {
+                    respond_blocking(move || http::transaction(&identity, &bus_id.into(), &txn), context)
+                })
+            }
+
+            // I2cBusSmbus - POST /i2c/{busId}/smbus/{addr}
+            Method::POST if paths::REGEX_I2C_BUSID_SMBUS_ADDR.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_SMBUS_ADDR;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                json_body(req, move |op, context| {
+                    respond_blocking(move || http::smbus(&identity, &bus_id.into(), &addr.into(), &op), context)
+                })
+            }
+
+            // I2cBusReadByte - GET /i2c/{busId}/read/byte/{addr}
+            Method::GET if paths::REGEX_I2C_BUSID_READ_BYTE_ADDR.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_READ_BYTE_ADDR;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(move || http::read_byte(&identity, &bus_id.into(), &addr.into()), context)
+            }
+
+            // I2cBusReadBytes - GET /i2c/{busId}/read/bytes/{addr}/{numBytes}
+            Method::GET if paths::REGEX_I2C_BUSID_READ_BYTES_ADDR_NUMBYTES.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_READ_BYTES_ADDR_NUMBYTES;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let num_bytes: i32 = match path_param(re, &path, "numBytes") {
+                    Ok(num_bytes) => num_bytes,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(
+                    move || http::read_bytes(&identity, &bus_id.into(), &addr.into(), &num_bytes.into()),
+                    context,
+                )
+            }
+
+            // I2cBusReadReg - GET /i2c/{busId}/read/reg/{addr}/{reg}/{numBytes}
+            Method::GET if paths::REGEX_I2C_BUSID_READ_REG_ADDR_REG_NUMBYTES.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_READ_REG_ADDR_REG_NUMBYTES;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let reg: i32 = match path_param(re, &path, "reg") {
+                    Ok(reg) => reg,
+                    Err(rsp) => return rsp,
+                };
+                let num_bytes: i32 = match path_param(re, &path, "numBytes") {
+                    Ok(num_bytes) => num_bytes,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(
+                    move || {
+                        let num_bytes = num_bytes.into();
+                        http::read_reg(&identity, &bus_id.into(), &addr.into(), &reg.into(), &num_bytes, &args)
+                    },
+                    context,
+                )
+            }
+
+            // I2cBusWriteByteReg - POST /i2c/{busId}/write/byte/reg/{addr}/{reg}/{value}
+            Method::POST if paths::REGEX_I2C_BUSID_WRITE_BYTE_REG_ADDR_REG_VALUE.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_WRITE_BYTE_REG_ADDR_REG_VALUE;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let reg: i32 = match path_param(re, &path, "reg") {
+                    Ok(reg) => reg,
+                    Err(rsp) => return rsp,
+                };
+                let value: i32 = match path_param(re, &path, "value") {
+                    Ok(value) => value,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(
+                    move || {
+                        http::write_byte_reg(&identity, &bus_id.into(), &addr.into(), &reg.into(), &value.into(), &args)
+                    },
+                    context,
+                )
+            }
+
+            // I2cBusWriteByte - POST /i2c/{busId}/write/byte/{addr}/{value}
+            Method::POST if paths::REGEX_I2C_BUSID_WRITE_BYTE_ADDR_VALUE.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_WRITE_BYTE_ADDR_VALUE;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let value: i32 = match path_param(re, &path, "value") {
+                    Ok(value) => value,
+                    Err(rsp) => return rsp,
+                };
+                respond_blocking(
+                    move || http::write_byte(&identity, &bus_id.into(), &addr.into(), &value.into()),
+                    context,
+                )
+            }
+
+            // I2cBusWriteBytesReg - POST /i2c/{busId}/write/bytes/reg/{addr}/{reg}
+            Method::POST if paths::REGEX_I2C_BUSID_WRITE_BYTES_REG_ADDR_REG.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_WRITE_BYTES_REG_ADDR_REG;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                let reg: i32 = match path_param(re, &path, "reg") {
+                    Ok(reg) => reg,
+                    Err(rsp) => return rsp,
+                };
+                let args = match query_params(req.uri()) {
+                    Ok(args) => args,
+                    Err(rsp) => return rsp,
+                };
+                json_body(req, move |values, context| {
+                    respond_blocking(
+                        move || {
+                            http::write_bytes_reg(&identity, &bus_id.into(), &addr.into(), &reg.into(), &values, &args)
+                        },
+                        context,
+                    )
+                })
+            }
+
+            // I2cBusWriteBytes - POST /i2c/{busId}/write/bytes/{addr}
+            Method::POST if paths::REGEX_I2C_BUSID_WRITE_BYTES_ADDR.is_match(&path) => {
+                let re = &paths::REGEX_I2C_BUSID_WRITE_BYTES_ADDR;
+                let bus_id: i32 = match path_param(re, &path, "busId") {
+                    Ok(bus_id) => bus_id,
+                    Err(rsp) => return rsp,
+                };
+                let addr: i32 = match path_param(re, &path, "addr") {
+                    Ok(addr) => addr,
+                    Err(rsp) => return rsp,
+                };
+                json_body(req, move |values, context| {
+                    respond_blocking(
+                        move || http::write_bytes(&identity, &bus_id.into(), &addr.into(), &values),
+                        context,
+                    )
+                })
+            }
+
+            _ => hyper::service::Service::call(&mut self.inner, req),
+        };
+        with_timeout(rsp, span_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forbidden() -> I2cBusReadBytesResponse {
+        I2cBusReadBytesResponse::Forbidden(models::I2cBusForbidden {
+            error: Some(13),
+            description: Some("Forbidden".to_string()),
+            rule: None,
+        })
+    }
+
+    fn body(response: Response<Body>) -> String {
+        let body = response.into_body().concat2().wait().unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn response_has_status_headers_and_body() {
+        let response = response(forbidden(), &XSpanIdString("span".to_string()));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.headers()["x-span-id"], "span");
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(body(response), r#"{"error":13,"description":"Forbidden","rule":null}"#);
+    }
+
+    #[test]
+    fn text_dump_is_plain_text() {
+        let rsp = I2cBusDumpResponse::OKText("00: ff\n".to_string());
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert_eq!(rsp.content_type(), "text/plain");
+        assert_eq!(rsp.body(), b"00: ff\n".to_vec());
+    }
+
+    #[test]
+    fn forbidden_is_an_api_error() {
+        let rsp: Result<i2cbus_api::I2cBusReadBytesResponse, i2cbus_api::ApiError> = forbidden().into();
+        assert!(rsp.is_err());
+        let rsp = I2cBusReadBytesResponse::BadRequest(i2cbus_api::models::I2cBusArg {
+            arg: Some("addr".to_string()),
+            description: None,
+        });
+        let rsp: Result<i2cbus_api::I2cBusReadBytesResponse, i2cbus_api::ApiError> = rsp.into();
+        assert!(matches!(rsp, Ok(i2cbus_api::I2cBusReadBytesResponse::BadRequest(_))));
+    }
+
+    #[test]
+    fn path_params_are_decoded_and_parsed() {
+        let regex = &paths::REGEX_I2C_BUS_BUSID_DEVICE_ADDR_REG_NAME;
+        let path = "/i2c/bus/1/device/32/reg/LED%20OUT";
+        assert_eq!(path_param::<i32>(regex, path, "addr").ok(), Some(32));
+        assert_eq!(path_param::<String>(regex, path, "name").ok(), Some("LED OUT".to_string()));
+        match path_param::<i32>(regex, "/i2c/bus/1/device/x/reg/a", "addr") {
+            Err(rsp) => assert_eq!(rsp.wait().unwrap().status(), StatusCode::BAD_REQUEST),
+            Ok(addr) => panic!("parsed {}", addr),
+        }
+    }
+
+    #[test]
+    fn query_params_are_parsed() {
+        let uri = "/i2c/bus/0/scan?first=8&mode=read".parse::<Uri>().unwrap();
+        let args = query_params::<models::I2cBusScanArgs>(&uri).ok().unwrap();
+        assert_eq!(args.first, Some(8));
+        assert_eq!(args.last, None);
+        assert_eq!(args.mode, Some("read".to_string()));
+        let uri = "/i2c/bus/0/scan?first=x".parse::<Uri>().unwrap();
+        assert!(query_params::<models::I2cBusScanArgs>(&uri).is_err());
+    }
+
+    #[test]
+    fn bus_routes_match() {
+        assert!(paths::REGEX_I2C_BUS_RESCAN.is_match("/i2c/bus/rescan"));
+        let caps = paths::REGEX_I2C_BUS_BUSID.captures("/i2c/bus/i2c-1/scan").unwrap();
+        assert_eq!((&caps["busId"], &caps["rest"]), ("i2c-1", "scan"));
+        assert!(paths::REGEX_I2C_BUS_BUSID.captures("/i2c/bus/rescan").is_none());
+    }
+}
@@ -0,0 +1,597 @@
+//! Models for the routes and fields served by ext, in the style of
+//! i2cbus_api::models.
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A list of available I2C buses, including the adapter name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusList {
+    #[serde(rename = "path")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    #[serde(rename = "id")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+
+    #[serde(rename = "name")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Adapter functionality, e.g. i2c, 10bit_addr, smbus_pec
+    #[serde(rename = "funcs")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub funcs: Option<Vec<String>>,
+}
+
+impl From<I2cBusList> for i2cbus_api::models::I2cBusList {
+    fn from(bus: I2cBusList) -> Self {
+        i2cbus_api::models::I2cBusList {
+            path: bus.path,
+            id: bus.id,
+        }
+    }
+}
+
+/// Query parameters for a bus scan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusScanArgs {
+    /// First address to probe, default 0x03
+    #[serde(rename = "first")]
+    pub first: Option<i32>,
+
+    /// Last address to probe, default 0x77
+    #[serde(rename = "last")]
+    pub last: Option<i32>,
+
+    /// One of auto (the default), quick or read
+    #[serde(rename = "mode")]
+    pub mode: Option<String>,
+}
+
+/// The results of a bus scan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusScan {
+    /// Addresses which responded to the probe
+    #[serde(rename = "found")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found: Option<Vec<i32>>,
+
+    /// Addresses which weren't probed, as a kernel driver has claimed them
+    #[serde(rename = "claimed")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claimed: Option<Vec<i32>>,
+}
+
+/// Query parameters for a register dump
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusDumpArgs {
+    /// First register to read, default 0
+    #[serde(rename = "first")]
+    pub first: Option<i32>,
+
+    /// Last register to read, default 255
+    #[serde(rename = "last")]
+    pub last: Option<i32>,
+
+    /// One of byte (the default), word or block
+    #[serde(rename = "mode")]
+    pub mode: Option<String>,
+
+    /// One of json (the default) or text, for i2cdump's hex and ASCII layout
+    #[serde(rename = "format")]
+    pub format: Option<String>,
+}
+
+/// A row of a register dump, of up to 16 registers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusDumpRow {
+    /// First register in this row
+    #[serde(rename = "reg")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg: Option<i32>,
+
+    /// Register values, null where the register couldn't be read
+    #[serde(rename = "values")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<Option<i32>>>,
+}
+
+/// A register dump
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusDump {
+    #[serde(rename = "rows")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<Vec<I2cBusDumpRow>>,
+}
+
+/// A segment of a combined transaction - either a write or a read
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusSegment {
+    /// I2C slave address for this segment
+    #[serde(rename = "addr")]
+    pub addr: Option<i32>,
+
+    /// Values to write, for a write segment
+    #[serde(rename = "write")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write: Option<Vec<i32>>,
+
+    /// Number of bytes to read, for a read segment
+    #[serde(rename = "read")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read: Option<i32>,
+
+    /// How to wait for the device to ACK, for a wait segment
+    #[serde(rename = "wait")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait: Option<I2cBusWait>,
+}
+
+/// An ordered list of segments to be issued as a single combined transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusTransaction {
+    #[serde(rename = "segments")]
+    pub segments: Option<Vec<I2cBusSegment>>,
+}
+
+/// The result of a segment of a combined transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusSegmentResult {
+    /// Values read, for a read segment
+    #[serde(rename = "values")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<i32>>,
+}
+
+/// The result of a combined transaction, with a result for each segment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusTransactionResult {
+    #[serde(rename = "ok")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ok: Option<i32>,
+
+    #[serde(rename = "segments")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segments: Option<Vec<I2cBusSegmentResult>>,
+}
+
+/// An SMBus operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusSmbus {
+    /// One of quick, receive_byte, send_byte, read_byte_data,
+    /// write_byte_data, read_word_data, write_word_data, process_call,
+    /// block_read, block_write or block_process_call
+    #[serde(rename = "op")]
+    pub op: Option<String>,
+
+    /// Command (register), for all operations other than quick, receive_byte
+    /// and send_byte
+    #[serde(rename = "command")]
+    pub command: Option<i32>,
+
+    /// Byte or word to send, or for quick, 1 to send a read bit and 0 (the
+    /// default) to send a write bit
+    #[serde(rename = "value")]
+    pub value: Option<i32>,
+
+    /// Bytes to send, for block operations
+    #[serde(rename = "values")]
+    pub values: Option<Vec<i32>>,
+}
+
+/// The result of an SMBus operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusSmbusResult {
+    /// Byte or word received
+    #[serde(rename = "value")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<i32>,
+
+    /// Bytes received, for block operations
+    #[serde(rename = "values")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<i32>>,
+}
+
+/// A read-modify-write of a register
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusModify {
+    /// One of update (the default), which replaces the bits in mask with
+    /// those of value, or set, clear or toggle, which set, clear or toggle
+    /// the bits in mask
+    #[serde(rename = "op")]
+    pub op: Option<String>,
+
+    #[serde(rename = "mask")]
+    pub mask: Option<i32>,
+
+    /// New value of the bits in mask, for update
+    #[serde(rename = "value")]
+    pub value: Option<i32>,
+}
+
+/// The value of a register before and after a read-modify-write
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusModifyResult {
+    #[serde(rename = "before")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<i32>,
+
+    #[serde(rename = "after")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<i32>,
+}
+
+/// Why an operation was forbidden by the authorisation policy, or as it's a
+/// write and writes are disabled or the target is write-protected
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusForbidden {
+    /// EACCES (13) if forbidden by the policy, or EROFS (30) for writes
+    /// refused in read-only mode or to write-protected ranges
+    #[serde(rename = "error")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<i32>,
+
+    #[serde(rename = "description")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// The policy rule which forbade the operation, as file:line: rule (null
+    /// if no rule permitted it), or the setting which refused the write
+    #[serde(rename = "rule")]
+    pub rule: Option<String>,
+}
+
+/// Liveness
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusHealth {
+    /// Always ok
+    #[serde(rename = "status")]
+    pub status: Option<String>,
+}
+
+/// Readiness, and what it was decided from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusReady {
+    #[serde(rename = "ready")]
+    pub ready: Option<bool>,
+
+    /// Whether the last search for buses succeeded
+    #[serde(rename = "enumerated")]
+    pub enumerated: Option<bool>,
+
+    /// Number of buses open
+    #[serde(rename = "buses")]
+    pub buses: Option<i32>,
+
+    /// Number of threads bus operations run on, and how many are busy
+    #[serde(rename = "workers")]
+    pub workers: Option<i32>,
+
+    #[serde(rename = "busy")]
+    pub busy: Option<i32>,
+
+    /// Whether every worker is busy, so operations are queueing
+    #[serde(rename = "saturated")]
+    pub saturated: Option<bool>,
+
+    /// The required buses and devices
+    #[serde(rename = "required")]
+    pub required: Option<Vec<I2cBusRequired>>,
+}
+
+/// A bus, or a device on it, required for readiness
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusRequired {
+    #[serde(rename = "bus")]
+    pub bus: Option<i32>,
+
+    /// The device's address, if a device rather than just the bus is required
+    #[serde(rename = "addr")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr: Option<i32>,
+
+    /// Whether the bus is present or device responds, or null if the device
+    /// wasn't probed as all workers were busy
+    #[serde(rename = "present")]
+    pub present: Option<bool>,
+}
+
+/// A device, and the profile bound to it, if any.  Also the body used to
+/// bind a profile, when only profile is needed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusDevice {
+    #[serde(rename = "bus")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bus: Option<i32>,
+
+    #[serde(rename = "addr")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr: Option<i32>,
+
+    /// Name of the profile, or null if none is bound
+    #[serde(rename = "profile")]
+    pub profile: Option<String>,
+}
+
+/// The value of a named register, and of each of its fields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusNamedReg {
+    #[serde(rename = "name")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(rename = "reg")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg: Option<i64>,
+
+    #[serde(rename = "value")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<i64>,
+
+    #[serde(rename = "fields")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, i64>>,
+}
+
+/// A write to a named register - the whole value, and/or values for some of
+/// its fields, which without a value are written into the current value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusNamedRegWrite {
+    #[serde(rename = "value")]
+    pub value: Option<i64>,
+
+    #[serde(rename = "fields")]
+    pub fields: Option<BTreeMap<String, i64>>,
+}
+
+/// How the register of a register read or write is sent to the device
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusRegArgs {
+    /// Register pointer width in bytes - 1 (the default), 2 or 4
+    #[serde(rename = "regWidth")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg_width: Option<i32>,
+
+    /// Register pointer byte order - big (the default) or little
+    #[serde(rename = "regEndian")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg_endian: Option<String>,
+}
+
+/// How a typed value is read from or written to a register - its type, byte
+/// order and scaling - and how the register is sent to the device
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusValueArgs {
+    /// One of u8 (the default), i8, u16, i16, u24, i24, u32 or i32
+    #[serde(rename = "type")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+
+    /// Byte order of the value - big (the default) or little
+    #[serde(rename = "endian")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endian: Option<String>,
+
+    /// The value is raw * scale + offset, with scale defaulting to 1
+    #[serde(rename = "scale")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+
+    #[serde(rename = "offset")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+
+    #[serde(rename = "regWidth")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg_width: Option<i32>,
+
+    #[serde(rename = "regEndian")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg_endian: Option<String>,
+}
+
+/// A typed value to write, in the same units as read (after scaling)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusValueWrite {
+    #[serde(rename = "value")]
+    pub value: Option<f64>,
+}
+
+/// A typed value read or written, as the raw integer and scaled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusValue {
+    #[serde(rename = "raw")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<i64>,
+
+    #[serde(rename = "value")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+}
+
+/// How a chunked read or write is split into transfers
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusChunkedArgs {
+    /// Most bytes per message, including the register pointer for writes -
+    /// defaults to the adapter limit
+    #[serde(rename = "chunk")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<i32>,
+
+    /// Device page size - writes are split so no write crosses a page
+    #[serde(rename = "page")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<i32>,
+
+    /// Whether the register is advanced for each chunk (the default), or
+    /// sent unchanged before every chunk
+    #[serde(rename = "regIncrement")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg_increment: Option<bool>,
+
+    #[serde(rename = "regWidth")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg_width: Option<i32>,
+
+    #[serde(rename = "regEndian")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reg_endian: Option<String>,
+}
+
+/// The result of a chunked read or write
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusChunked {
+    /// Number of transfers the data was split into
+    #[serde(rename = "chunks")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<i32>,
+
+    /// Values read, for a read
+    #[serde(rename = "values")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<i32>>,
+}
+
+/// A chunked read or write which failed part way through
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusChunkedError {
+    #[serde(rename = "error")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<i32>,
+
+    #[serde(rename = "description")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Offset of the chunk which failed - the bytes before it were
+    /// transferred
+    #[serde(rename = "offset")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+
+    /// Values read before the failure, for a read
+    #[serde(rename = "values")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<i32>>,
+}
+
+/// A type of EEPROM which can be read and programmed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusEepromType {
+    #[serde(rename = "name")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Size in bytes
+    #[serde(rename = "size")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+
+    /// Page size in bytes
+    #[serde(rename = "page")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<i32>,
+
+    /// Width of the memory address sent after the slave address, in bytes
+    #[serde(rename = "pointerWidth")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pointer_width: Option<i32>,
+
+    /// Number of consecutive slave addresses the device occupies
+    #[serde(rename = "addresses")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addresses: Option<i32>,
+}
+
+/// How an EEPROM is read or programmed
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusEepromArgs {
+    /// EEPROM type, e.g. 24c256
+    #[serde(rename = "type")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+
+    /// Image format - bin (the default) or hex (Intel HEX)
+    #[serde(rename = "format")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+
+    /// Offset to start at, for a read or a binary image
+    #[serde(rename = "offset")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+
+    /// Number of bytes to read - defaults to the rest of the EEPROM
+    #[serde(rename = "length")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<i64>,
+
+    /// Page size, overriding the type's
+    #[serde(rename = "page")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<i32>,
+
+    /// Whether to read back and compare what was written (default true)
+    #[serde(rename = "verify")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<bool>,
+
+    /// Longest to wait for a write cycle to complete, in milliseconds
+    #[serde(rename = "writeTimeout")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_timeout: Option<i32>,
+
+    /// How often to poll for the end of a write cycle, in milliseconds
+    #[serde(rename = "pollInterval")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll_interval: Option<i32>,
+}
+
+/// The result of programming an EEPROM
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusEepromWritten {
+    /// Number of bytes written
+    #[serde(rename = "bytes")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<i64>,
+
+    /// Number of page writes
+    #[serde(rename = "writes")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub writes: Option<i32>,
+
+    #[serde(rename = "verified")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+}
+
+/// How to wait for a device to ACK its address
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusWait {
+    /// How to probe the device - auto (the default), quick or read
+    #[serde(rename = "mode")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+
+    /// Longest to wait, in milliseconds (default 100)
+    #[serde(rename = "timeout")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<i32>,
+
+    /// How often to probe the device, in milliseconds (default 1)
+    #[serde(rename = "interval")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<i32>,
+}
+
+/// The result of waiting for a device to ACK
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cBusWaitResult {
+    /// How long the device took to ACK, in milliseconds
+    #[serde(rename = "waited")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub waited: Option<f64>,
+}
@@ -0,0 +1,89 @@
+use super::BusError;
+use nix::libc::c_ulong;
+use nix::{convert_ioctl_res, ioctl_read_bad};
+use std::os::unix::io::RawFd;
+
+// Adapter functionality flags, from linux/i2c.h
+
+pub(crate) const I2C_FUNC_I2C: u64 = 0x0000_0001;
+pub(crate) const I2C_FUNC_10BIT_ADDR: u64 = 0x0000_0002;
+pub(crate) const I2C_FUNC_PROTOCOL_MANGLING: u64 = 0x0000_0004;
+pub(crate) const I2C_FUNC_SMBUS_PEC: u64 = 0x0000_0008;
+pub(crate) const I2C_FUNC_NOSTART: u64 = 0x0000_0010;
+pub(crate) const I2C_FUNC_SLAVE: u64 = 0x0000_0020;
+pub(crate) const I2C_FUNC_SMBUS_BLOCK_PROC_CALL: u64 = 0x0000_8000;
+pub(crate) const I2C_FUNC_SMBUS_QUICK: u64 = 0x0001_0000;
+pub(crate) const I2C_FUNC_SMBUS_READ_BYTE: u64 = 0x0002_0000;
+pub(crate) const I2C_FUNC_SMBUS_WRITE_BYTE: u64 = 0x0004_0000;
+pub(crate) const I2C_FUNC_SMBUS_READ_BYTE_DATA: u64 = 0x0008_0000;
+pub(crate) const I2C_FUNC_SMBUS_WRITE_BYTE_DATA: u64 = 0x0010_0000;
+pub(crate) const I2C_FUNC_SMBUS_READ_WORD_DATA: u64 = 0x0020_0000;
+pub(crate) const I2C_FUNC_SMBUS_WRITE_WORD_DATA: u64 = 0x0040_0000;
+pub(crate) const I2C_FUNC_SMBUS_PROC_CALL: u64 = 0x0080_0000;
+pub(crate) const I2C_FUNC_SMBUS_READ_BLOCK_DATA: u64 = 0x0100_0000;
+pub(crate) const I2C_FUNC_SMBUS_WRITE_BLOCK_DATA: u64 = 0x0200_0000;
+pub(crate) const I2C_FUNC_SMBUS_READ_I2C_BLOCK: u64 = 0x0400_0000;
+pub(crate) const I2C_FUNC_SMBUS_WRITE_I2C_BLOCK: u64 = 0x0800_0000;
+pub(crate) const I2C_FUNC_SMBUS_HOST_NOTIFY: u64 = 0x1000_0000;
+
+// All the SMBus operations, as supported by most SMBus and I2C adapters
+pub(crate) const I2C_FUNC_SMBUS_ALL: u64 = I2C_FUNC_SMBUS_QUICK
+    | I2C_FUNC_SMBUS_READ_BYTE
+    | I2C_FUNC_SMBUS_WRITE_BYTE
+    | I2C_FUNC_SMBUS_READ_BYTE_DATA
+    | I2C_FUNC_SMBUS_WRITE_BYTE_DATA
+    | I2C_FUNC_SMBUS_READ_WORD_DATA
+    | I2C_FUNC_SMBUS_WRITE_WORD_DATA
+    | I2C_FUNC_SMBUS_PROC_CALL
+    | I2C_FUNC_SMBUS_READ_BLOCK_DATA
+    | I2C_FUNC_SMBUS_WRITE_BLOCK_DATA
+    | I2C_FUNC_SMBUS_BLOCK_PROC_CALL
+    | I2C_FUNC_SMBUS_READ_I2C_BLOCK
+    | I2C_FUNC_SMBUS_WRITE_I2C_BLOCK;
+
+// Name of each flag, as exposed via the API
+const NAMES: [(u64, &str); 20] = [
+    (I2C_FUNC_I2C, "i2c"),
+    (I2C_FUNC_10BIT_ADDR, "10bit_addr"),
+    (I2C_FUNC_PROTOCOL_MANGLING, "protocol_mangling"),
+    (I2C_FUNC_SMBUS_PEC, "smbus_pec"),
+    (I2C_FUNC_NOSTART, "nostart"),
+    (I2C_FUNC_SLAVE, "slave"),
+    (I2C_FUNC_SMBUS_BLOCK_PROC_CALL, "smbus_block_proc_call"),
+    (I2C_FUNC_SMBUS_QUICK, "smbus_quick"),
+    (I2C_FUNC_SMBUS_READ_BYTE, "smbus_read_byte"),
+    (I2C_FUNC_SMBUS_WRITE_BYTE, "smbus_write_byte"),
+    (I2C_FUNC_SMBUS_READ_BYTE_DATA, "smbus_read_byte_data"),
+    (I2C_FUNC_SMBUS_WRITE_BYTE_DATA, "smbus_write_byte_data"),
+    (I2C_FUNC_SMBUS_READ_WORD_DATA, "smbus_read_word_data"),
+    (I2C_FUNC_SMBUS_WRITE_WORD_DATA, "smbus_write_word_data"),
+    (I2C_FUNC_SMBUS_PROC_CALL, "smbus_proc_call"),
+    (I2C_FUNC_SMBUS_READ_BLOCK_DATA, "smbus_read_block_data"),
+    (I2C_FUNC_SMBUS_WRITE_BLOCK_DATA, "smbus_write_block_data"),
+    (I2C_FUNC_SMBUS_READ_I2C_BLOCK, "smbus_read_i2c_block"),
+    (I2C_FUNC_SMBUS_WRITE_I2C_BLOCK, "smbus_write_i2c_block"),
+    (I2C_FUNC_SMBUS_HOST_NOTIFY, "smbus_host_notify"),
+];
+
+// Returns the names of the flags set in funcs
+pub(crate) fn names(funcs: u64) -> Vec<&'static str> {
+    NAMES
+        .iter()
+        .filter(|(flag, _)| funcs & flag != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+const I2C_FUNCS: u16 = 0x0705;
+
+ioctl_read_bad!(i2c_funcs, I2C_FUNCS, c_ulong);
+
+// Queries the adapter's functionality using the I2C_FUNCS ioctl on the bus's
+// file descriptor
+pub(crate) fn funcs(fd: RawFd) -> Result<u64, BusError> {
+    let mut funcs: c_ulong = 0;
+    unsafe {
+        i2c_funcs(fd, &mut funcs).map_err(i2cdev2::linux::LinuxI2CError::from)?;
+    }
+    Ok(funcs as u64)
+}
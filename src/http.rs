@@ -1,34 +1,289 @@
+#[path = "eeprom.rs"] mod eeprom;
 #[path = "i2c.rs"] mod i2c;
-use i2cbus_api::models;
-use i2cbus_api::{
-    I2cBusApiResponse, I2cBusListResponse, I2cBusReadByteResponse, I2cBusReadBytesResponse,
-    I2cBusReadRegResponse, I2cBusWriteByteRegResponse, I2cBusWriteByteResponse,
-    I2cBusWriteBytesRegResponse, I2cBusWriteBytesResponse,
+#[path = "metrics.rs"] mod metrics;
+use super::ext;
+use super::ext::{
+    I2cBusChunkedResponse, I2cBusDeviceResponse, I2cBusDumpResponse, I2cBusEepromReadResponse,
+    I2cBusEepromTypesResponse, I2cBusEepromWriteResponse, I2cBusInfoResponse, I2cBusModifyResponse,
+    I2cBusNamedRegResponse,
+    I2cBusReadByteResponse, I2cBusReadBytesResponse, I2cBusReadRegResponse, I2cBusScanResponse,
+    I2cBusSmbusResponse, I2cBusTransactionResponse, I2cBusValueResponse, I2cBusWaitResponse,
+    I2cBusWriteByteRegResponse,
+    I2cBusWriteByteResponse, I2cBusWriteBytesRegResponse, I2cBusWriteBytesResponse,
 };
+use super::policy::{self, Access, Policy, Range, Target, WriteProtect};
+use super::profile::{self, Profile, RegAccess, Register};
+use i2c::smbus::{SmbusData, SmbusOp};
+use i2cbus_api::models;
+use i2cbus_api::I2cBusApiResponse;
+use arc_swap::ArcSwap;
+use futures_cpupool::{CpuFuture, CpuPool};
+use glob::Pattern;
+use inotify::{Inotify, WatchMask};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::sync::Mutex;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use lazy_static::lazy_static;
 use log::{info, trace, warn};
 
-// Global used to store BUSES - is initialized first time it is used
+// Global used to store BUSES - is initialized first time it is used.  Each
+// bus has its own lock (see BusInfo::lock), so requests to different buses
+// run in parallel.  When buses are added or removed the whole table is
+// replaced, so reading it never takes a lock.
+type BusTable = Vec<Arc<i2c::BusInfo>>;
 lazy_static! {
-    static ref BUSES: Mutex<Vec<i2c::BusInfo>> = Mutex::new(init_buses());
+    static ref BUSES: ArcSwap<BusTable> = ArcSwap::from_pointee(init_buses());
+    static ref RESCAN: Mutex<()> = Mutex::new(());
+}
+
+// Returns the current bus table
+fn buses() -> Arc<BusTable> {
+    BUSES.load_full()
 }
 
-impl<'a> From<&'a i2c::BusInfo> for models::I2cBusList {
+impl<'a> From<&'a i2c::BusInfo> for ext::models::I2cBusList {
     fn from(bus: &i2c::BusInfo) -> Self {
-        models::I2cBusList {
+        ext::models::I2cBusList {
             id: Some(bus.id as i32),
             path: Some(bus.path.clone()),
+            name: bus.name.clone(),
+            funcs: bus.funcs.map(|funcs| {
+                i2c::funcs::names(funcs)
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect()
+            }),
+        }
+    }
+}
+
+// Environment variables setting the number of threads in the pool which bus
+// operations run on, and how long a request may take before it times out
+pub(crate) const I2C_WORKERS_VAR: &str = "I2C_WORKERS";
+pub(crate) const I2C_TIMEOUT_VAR: &str = "I2C_TIMEOUT";
+const DEF_WORKERS: usize = 8;
+const DEF_TIMEOUT_MS: u64 = 5000;
+
+// Bus operations block in the kernel, potentially for a long time if a device
+// is clock stretching or the bus is stuck, so are run on a dedicated pool
+// rather than on the hyper event loop
+lazy_static! {
+    static ref WORKERS: usize = env_num(I2C_WORKERS_VAR, DEF_WORKERS);
+    static ref POOL: CpuPool = CpuPool::new(*WORKERS);
+    static ref TIMEOUT: Duration =
+        Duration::from_millis(env_num(I2C_TIMEOUT_VAR, DEF_TIMEOUT_MS));
+}
+
+// Environment variable setting the most bytes to send to or receive from a
+// device in one message, for adapters with a lower limit than the kernel's.
+// Chunked transfers are split into messages no larger than this.
+pub(crate) const I2C_MAX_TRANSFER_VAR: &str = "I2C_MAX_TRANSFER";
+
+// Most bytes a chunked transfer may read or write in total
+const MAX_CHUNKED_LEN: usize = 1 << 20;
+
+// Fewest bytes per message - each chunk of a write must have room for the
+// longest (4 byte) register pointer and at least one byte of data
+const MIN_TRANSFER: usize = 5;
+
+lazy_static! {
+    static ref MAX_TRANSFER: usize = load_max_transfer();
+}
+
+fn load_max_transfer() -> usize {
+    match env_num(I2C_MAX_TRANSFER_VAR, i2c::MAX_MSG_LEN) {
+        max if max < MIN_TRANSFER => {
+            warn!("Invalid {} {}, using {}", I2C_MAX_TRANSFER_VAR, max, MIN_TRANSFER);
+            MIN_TRANSFER
+        }
+        max => std::cmp::min(max, i2c::MAX_MSG_LEN),
+    }
+}
+
+// Loads the largest message size, which server::create calls at startup so
+// an invalid size is reported immediately
+pub(crate) fn init_transfers() {
+    lazy_static::initialize(&MAX_TRANSFER);
+}
+
+// Returns the value of a numeric environment variable, or the default if it
+// isn't set or isn't valid
+fn env_num<T>(var: &str, default: T) -> T
+where
+    T: FromStr + Copy + PartialOrd + From<u8>,
+{
+    match env::var(var) {
+        Ok(val) => match val.parse::<T>() {
+            Ok(num) if num > T::from(0) => num,
+            _ => {
+                warn!("Invalid {} {}, using default", var, val);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+// Runs a bus operation on the blocking pool.  If the returned future is
+// dropped (e.g. on timeout) before the operation starts, it's cancelled.
+pub(crate) fn blocking<F, R, E>(f: F) -> CpuFuture<R, E>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+    E: Send + 'static,
+{
+    POOL.spawn_fn(move || {
+        let _busy = Busy::new();
+        Ok(f())
+    })
+}
+
+// Number of pool threads currently carrying out an operation
+static BUSY: AtomicUsize = AtomicUsize::new(0);
+
+// Counts a pool thread as busy while it exists, so the count is kept right
+// even if the operation panics
+struct Busy;
+
+impl Busy {
+    fn new() -> Busy {
+        BUSY.fetch_add(1, Ordering::SeqCst);
+        Busy
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        BUSY.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// How many pool threads there are, and how many are busy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PoolStatus {
+    workers: usize,
+    busy: usize,
+}
+
+impl PoolStatus {
+    // Whether every pool thread is busy, so further operations will queue
+    pub(crate) fn saturated(&self) -> bool {
+        self.busy >= self.workers
+    }
+}
+
+pub(crate) fn pool_status() -> PoolStatus {
+    PoolStatus {
+        workers: *WORKERS,
+        busy: BUSY.load(Ordering::SeqCst),
+    }
+}
+
+// How long a request may take before it is failed with a timeout.  A bus
+// operation already under way when the timeout expires can't be
+// interrupted, so it completes in the background, holding the bus lock.
+pub(crate) fn timeout() -> Duration {
+    *TIMEOUT
+}
+
+// Environment variable which, if set, causes simulated buses to be used
+// instead of the real ones - see i2c::sim::init_buses for the format
+pub(crate) const I2C_SIM_VAR: &str = "I2C_SIM";
+
+// Environment variables selecting which buses are used - the directory to
+// search, a glob pattern bus filenames must match, and comma separated lists
+// of bus paths to allow (if set, no other buses are used) and deny
+pub(crate) const I2C_DEV_DIR_VAR: &str = "I2C_DEV_DIR";
+pub(crate) const I2C_DEV_PATTERN_VAR: &str = "I2C_DEV_PATTERN";
+pub(crate) const I2C_ALLOW_VAR: &str = "I2C_ALLOW";
+pub(crate) const I2C_DENY_VAR: &str = "I2C_DENY";
+const DEF_DEV_DIR: &str = "/dev/";
+const DEF_DEV_PATTERN: &str = "i2c-*";
+
+// Which buses to use, from the environment
+struct BusConfig {
+    dir: String,
+    pattern: Pattern,
+    allow: Option<Vec<String>>,
+    deny: Vec<String>,
+}
+
+impl BusConfig {
+    fn from_env() -> BusConfig {
+        let dir = env::var(I2C_DEV_DIR_VAR).unwrap_or_else(|_| DEF_DEV_DIR.to_string());
+        let pattern = env::var(I2C_DEV_PATTERN_VAR).unwrap_or_else(|_| DEF_DEV_PATTERN.to_string());
+        let pattern = match Pattern::new(&pattern) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                warn!("Invalid {} {} {}, using default", I2C_DEV_PATTERN_VAR, pattern, e);
+                Pattern::new(DEF_DEV_PATTERN).unwrap()
+            }
+        };
+        BusConfig {
+            dir,
+            pattern,
+            allow: env_list(I2C_ALLOW_VAR),
+            deny: env_list(I2C_DENY_VAR).unwrap_or_default(),
         }
     }
+
+    // Returns whether a filename in dir matches the pattern
+    fn matches(&self, name: &str) -> bool {
+        self.pattern.matches(name)
+    }
+
+    // Returns whether the bus at this path should be used
+    fn includes(&self, path: &str) -> bool {
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let allowed = match &self.allow {
+            Some(allow) => allow.iter().any(|allowed| allowed == path),
+            None => true,
+        };
+        self.matches(&name) && allowed && !self.deny.iter().any(|denied| denied == path)
+    }
 }
 
-// Called to initialize buses with appropriate /dev path
-fn init_buses() -> Vec<i2c::BusInfo> {
-    const DEV_DIR: &str = "/dev/";
-    const I2C_PATH_PREFIX: &str = "i2c-";
-    match i2c::init_buses(DEV_DIR, I2C_PATH_PREFIX) {
+// Returns the comma separated list in an environment variable, if set
+fn env_list(var: &str) -> Option<Vec<String>> {
+    env::var(var).ok().map(|list| {
+        list.split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+lazy_static! {
+    static ref BUS_CONFIG: BusConfig = BusConfig::from_env();
+}
+
+// Finds buses as specified by BUS_CONFIG, keeping any existing ones
+fn find_buses(existing: &[Arc<i2c::BusInfo>]) -> Result<BusTable, i2c::BusError> {
+    i2c::init_buses(&BUS_CONFIG.dir, &|path| BUS_CONFIG.includes(path), existing)
+}
+
+// Called to initialize buses with appropriate /dev path, or simulated buses
+// if I2C_SIM is set
+fn init_buses() -> BusTable {
+    let buses = match env::var(I2C_SIM_VAR) {
+        Ok(spec) => {
+            info!("Using simulated I2C buses {}", spec);
+            i2c::sim::init_buses(&spec).map(|buses| buses.into_iter().map(Arc::new).collect())
+        }
+        Err(_) => find_buses(&[]),
+    };
+    ENUMERATED.store(buses.is_ok(), Ordering::SeqCst);
+    match buses {
         Ok(buses) => buses,
         Err(e) => {
             println!("Error calling init_buses {}", e);
@@ -37,23 +292,280 @@ fn init_buses() -> Vec<i2c::BusInfo> {
     }
 }
 
+// Whether the last attempt to find buses, at startup or on a rescan,
+// succeeded
+static ENUMERATED: AtomicBool = AtomicBool::new(false);
+
+// Rescans for buses, replacing BUSES.  Buses which are still present
+// are kept as they are, so requests to them are unaffected, and those which
+// have gone are marked as removed, so in-flight requests to them fail.
+// Simulated buses never change.
+fn rescan_buses() {
+    if env::var(I2C_SIM_VAR).is_ok() {
+        return;
+    }
+    let _rescan = RESCAN.lock().unwrap();
+    let old = buses();
+    match find_buses(&old) {
+        Ok(new) => {
+            for bus in old.iter() {
+                if !new.iter().any(|new_bus| Arc::ptr_eq(bus, new_bus)) {
+                    info!("Removed {}", bus);
+                    bus.remove();
+                }
+            }
+            for bus in new.iter() {
+                if !old.iter().any(|old_bus| Arc::ptr_eq(bus, old_bus)) {
+                    info!("Added {}", bus);
+                }
+            }
+            BUSES.store(Arc::new(new));
+            ENUMERATED.store(true, Ordering::SeqCst);
+        }
+        Err(e) => {
+            warn!("Error rescanning buses {}", e);
+            ENUMERATED.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+// Starts a thread which watches the bus directory, rescanning whenever a bus is
+// added or removed (e.g. a USB-I2C bridge is plugged in, or a mux or
+// overlay is loaded)
+pub(crate) fn watch_buses() {
+    if env::var(I2C_SIM_VAR).is_ok() {
+        return;
+    }
+    let mut inotify = match Inotify::init() {
+        Ok(inotify) => inotify,
+        Err(e) => {
+            warn!("Not watching for I2C buses - inotify failed {}", e);
+            return;
+        }
+    };
+    let dir = &BUS_CONFIG.dir;
+    if let Err(e) = inotify.add_watch(dir, WatchMask::CREATE | WatchMask::DELETE) {
+        warn!("Not watching for I2C buses - couldn't watch {} {}", dir, e);
+        return;
+    }
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("Stopped watching for I2C buses {}", e);
+                    return;
+                }
+            };
+            let changed = events.into_iter().any(|event| {
+                match event.name.and_then(|name| name.to_str()) {
+                    Some(name) => BUS_CONFIG.matches(name),
+                    None => false,
+                }
+            });
+            if changed {
+                rescan_buses();
+            }
+        }
+    });
+}
+
+// Environment variable which, if set, is a comma separated list of buses (by
+// ID) and devices (as bus:addr) which must be present for the server to be
+// ready, e.g. "1,0:0x50"
+pub(crate) const I2C_REQUIRED_VAR: &str = "I2C_REQUIRED";
+
+// A bus, or a device on it, which readiness requires
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Required {
+    bus: usize,
+    addr: Option<u16>,
+}
+
+lazy_static! {
+    static ref REQUIRED: Vec<Required> = load_required();
+}
+
+fn load_required() -> Vec<Required> {
+    let required: Vec<Required> = env_list(I2C_REQUIRED_VAR)
+        .unwrap_or_default()
+        .iter()
+        .map(|item| {
+            parse_required(item)
+                .unwrap_or_else(|| panic!("Invalid {} entry {}", I2C_REQUIRED_VAR, item))
+        })
+        .collect();
+    if !required.is_empty() {
+        info!("Readiness requires {} buses and devices", required.len());
+    }
+    required
+}
+
+fn parse_required(item: &str) -> Option<Required> {
+    let mut fields = item.split(':');
+    let bus = fields.next().and_then(policy::parse_num)? as usize;
+    let addr = match fields.next() {
+        Some(addr) => Some(policy::parse_num(addr).and_then(|addr| u16::try_from(addr).ok())?),
+        None => None,
+    };
+    match fields.next() {
+        Some(_) => None,
+        None => Some(Required { bus, addr }),
+    }
+}
+
+// Loads the required buses and devices, which server::create calls at
+// startup so an invalid list is reported immediately
+pub(crate) fn init_readiness() {
+    lazy_static::initialize(&REQUIRED);
+}
+
+// Authorisation policy and write protection, loaded when first used, which
+// server::create ensures is at startup so invalid configuration is reported
+// immediately
+
+lazy_static! {
+    static ref POLICY: Option<Policy> = load_policy();
+    static ref WRITE_PROTECT: WriteProtect = load_write_protect();
+}
+
+fn load_policy() -> Option<Policy> {
+    match env::var(policy::I2C_POLICY_VAR) {
+        Ok(path) => {
+            let policy = Policy::load(&path).expect("Failed to load policy");
+            info!("Loaded {} policy rules from {}", policy.len(), path);
+            Some(policy)
+        }
+        Err(_) => {
+            warn!("No policy configured - any caller may access any bus");
+            None
+        }
+    }
+}
+
+fn load_write_protect() -> WriteProtect {
+    let read_only = match env::var(policy::I2C_READ_ONLY_VAR) {
+        Ok(val) => ["1", "true", "yes"].contains(&val.to_lowercase().as_str()),
+        Err(_) => false,
+    };
+    let spec = env::var(policy::I2C_PROTECT_VAR).unwrap_or_default();
+    let protect = WriteProtect::new(read_only, &spec).expect("Failed to parse write-protected ranges");
+    if protect.read_only() {
+        info!("Read-only mode - all writes are disabled");
+    }
+    if protect.len() > 0 {
+        info!("{} write-protected ranges {}", protect.len(), spec);
+    }
+    protect
+}
+
+pub(crate) fn init_policy() {
+    lazy_static::initialize(&POLICY);
+    lazy_static::initialize(&WRITE_PROTECT);
+}
+
+// Device profiles, loaded when first used (which server::create ensures is
+// at startup), and the profile bound to each device, keyed by bus ID and
+// address
+
+lazy_static! {
+    static ref PROFILES: HashMap<String, Profile> = load_profiles();
+    static ref DEVICES: Mutex<HashMap<(usize, u16), String>> = Mutex::new(load_devices());
+}
+
+fn load_profiles() -> HashMap<String, Profile> {
+    match env::var(profile::I2C_PROFILES_VAR) {
+        Ok(dir) => {
+            let profiles = profile::load(&dir).expect("Failed to load device profiles");
+            info!("Loaded {} device profiles from {}", profiles.len(), dir);
+            profiles
+        }
+        Err(_) => HashMap::new(),
+    }
+}
+
+fn load_devices() -> HashMap<(usize, u16), String> {
+    let mut devices = HashMap::new();
+    for item in env_list(profile::I2C_DEVICES_VAR).unwrap_or_default() {
+        let mut parts = item.splitn(2, '=');
+        let device = parts.next().and_then(parse_device);
+        match (device, parts.next()) {
+            (Some(device), Some(name)) if PROFILES.contains_key(name) => {
+                devices.insert(device, name.to_string());
+            }
+            _ => panic!("Invalid {} entry {}", profile::I2C_DEVICES_VAR, item),
+        }
+    }
+    devices
+}
+
+// Parses a device, as bus:addr
+fn parse_device(device: &str) -> Option<(usize, u16)> {
+    match parse_required(device) {
+        Some(Required {
+            bus,
+            addr: Some(addr),
+        }) => Some((bus, addr)),
+        _ => None,
+    }
+}
+
+pub(crate) fn init_profiles() {
+    lazy_static::initialize(&PROFILES);
+    lazy_static::initialize(&DEVICES);
+}
+
 // Arg errors
 
 enum ArgError {
     Error(models::I2cBusArg),
+    Forbidden(policy::Violation),
 }
 
 enum ArgErrorType {
     NoSuchBus,
+    AmbiguousBus,
     OutOfBounds,
     NoValues,
+    BadRange,
+    BadMode,
+    BadFormat,
+    BadSegment,
+    BadOp,
+    Missing,
+    Unsupported(u64),
+    NoSuchProfile,
+    NoProfile,
+    NoSuchRegister,
+    NoSuchField,
+    ReadOnly,
+    WriteOnly,
 }
 
 fn arg_err(arg: &str, val: &str, e_type: &ArgErrorType) -> ArgError {
-    let error = match e_type {
-        ArgErrorType::NoSuchBus => "no such bus",
-        ArgErrorType::OutOfBounds => "out of bounds",
-        ArgErrorType::NoValues => "no values",
+    let error: Cow<str> = match e_type {
+        ArgErrorType::NoSuchBus => "no such bus".into(),
+        ArgErrorType::AmbiguousBus => "more than one bus with this name".into(),
+        ArgErrorType::OutOfBounds => "out of bounds".into(),
+        ArgErrorType::NoValues => "no values".into(),
+        ArgErrorType::BadRange => "first address after last".into(),
+        ArgErrorType::BadMode => "unknown mode".into(),
+        ArgErrorType::BadFormat => "unknown format".into(),
+        ArgErrorType::BadSegment => "segment must have an addr and one of write, read or wait".into(),
+        ArgErrorType::BadOp => "unknown operation".into(),
+        ArgErrorType::Missing => "missing".into(),
+        ArgErrorType::NoSuchProfile => "no such profile".into(),
+        ArgErrorType::NoProfile => "no profile bound to this device".into(),
+        ArgErrorType::NoSuchRegister => "no such register in the device's profile".into(),
+        ArgErrorType::NoSuchField => "no such field in the register".into(),
+        ArgErrorType::ReadOnly => "register is read-only".into(),
+        ArgErrorType::WriteOnly => "register is write-only".into(),
+        ArgErrorType::Unsupported(funcs) => format!(
+            "adapter doesn't support {}",
+            i2c::funcs::names(*funcs).join(", ")
+        )
+        .into(),
     };
     ArgError::Error(models::I2cBusArg {
         arg: Some(arg.to_string()),
@@ -67,7 +579,7 @@ macro_rules! make_arg_check {
     ($fn_name:ident, $arg:ident, $arg_name:expr, $type:ty, $min:expr, $max:expr) => {
         fn $fn_name(val: &models::$arg) -> Result<$type, ArgError> {
             let val: i32 = val.clone().into();
-            if (val <= $max) && (val >= $min) {
+            if ($min..=$max).contains(&val) {
                 Ok(val as $type)
             } else {
                 Err(arg_err(
@@ -80,27 +592,168 @@ macro_rules! make_arg_check {
     };
 }
 
-make_arg_check!(check_arg_addr, Addr, "addr", u16, 0, 255);
+make_arg_check!(check_arg_scan_addr, Addr, "addr", u16, 0, 0x7f);
 make_arg_check!(check_arg_reg, Reg, "reg", u8, 0, 255);
 make_arg_check!(check_arg_value, Value, "value", u8, 0, 255);
 make_arg_check!(check_arg_num_bytes, NumBytes, "numBytes", u8, 0, 255);
 make_arg_check!(check_arg_byte, I2cByte, "I2cByte", u8, 0, 255);
 
-// Construct this function manually
-fn check_arg_bus_id(bus_id: &models::BusId) -> Result<usize, ArgError> {
-    let buses = BUSES.lock().unwrap();
+// Checks the register fits a register pointer of the requested width, 1 (the
+// default), 2 or 4 bytes, sent most significant byte first (big, the default)
+// or little endian.
+fn check_arg_reg_pointer(
+    reg: &models::Reg,
+    args: &ext::models::I2cBusRegArgs,
+) -> Result<i2c::RegPointer, ArgError> {
+    let width = match args.reg_width.unwrap_or(1) {
+        width if [1, 2, 4].contains(&width) => width as usize,
+        width => {
+            return Err(arg_err(
+                "regWidth",
+                format!("{}", width).as_str(),
+                &ArgErrorType::OutOfBounds,
+            ))
+        }
+    };
+    let lsb_first = match args.reg_endian.as_deref() {
+        None | Some("big") => false,
+        Some("little") => true,
+        Some(endian) => return Err(arg_err("regEndian", endian, &ArgErrorType::BadFormat)),
+    };
+    let reg: i32 = reg.clone().into();
+    if reg < 0 || reg as u32 > i2c::RegPointer::max(width) {
+        return Err(arg_err(
+            "reg",
+            format!("{}", reg).as_str(),
+            &ArgErrorType::OutOfBounds,
+        ));
+    }
+    Ok(i2c::RegPointer {
+        reg: reg as u32,
+        width,
+        lsb_first,
+    })
+}
+
+// Bus IDs are adapter numbers, so may not be contiguous - returns the bus
+// itself, which remains valid (though fails any operations) if it is removed
+// while the request is in progress.
+fn check_arg_bus_id(bus_id: &models::BusId) -> Result<Arc<i2c::BusInfo>, ArgError> {
     let bus_id: i32 = bus_id.clone().into();
-    if ((bus_id as usize) < buses.len()) && (bus_id >= 0) {
-        Ok(bus_id as usize)
-    } else {
-        Err(arg_err(
+    match buses().iter().find(|bus| bus.id as i32 == bus_id) {
+        Some(bus) => Ok(bus.clone()),
+        None => Err(arg_err(
             "busId",
             format!("{}", bus_id).as_str(),
             &ArgErrorType::NoSuchBus,
+        )),
+    }
+}
+
+// Checks the adapter supports the I2C_FUNC_* flags needed for an operation, so
+// unsupported operations are rejected before touching the bus.
+fn check_bus_funcs(bus: &i2c::BusInfo, funcs: u64) -> Result<(), ArgError> {
+    if bus.supports(funcs) {
+        Ok(())
+    } else {
+        let missing = funcs & !bus.funcs.unwrap_or(0);
+        Err(arg_err(
+            "busId",
+            format!("{}", bus.id).as_str(),
+            &ArgErrorType::Unsupported(missing),
         ))
     }
 }
 
+// Addresses are either 7-bit, 0x00-0x7f, or 10-bit, offset by ADDR_TEN_BIT as
+// the kernel does, so 0xa000-0xa3ff.  A 10-bit address needs an adapter which
+// supports 10-bit addressing.
+fn check_arg_addr(bus: &i2c::BusInfo, addr: &models::Addr) -> Result<u16, ArgError> {
+    let addr: i32 = addr.clone().into();
+    let ten_bit = i32::from(i2c::ADDR_TEN_BIT);
+    if !((0..=0x7f).contains(&addr) || (ten_bit..=ten_bit + 0x3ff).contains(&addr)) {
+        return Err(arg_err(
+            "addr",
+            format!("{}", addr).as_str(),
+            &ArgErrorType::OutOfBounds,
+        ));
+    }
+    let addr = addr as u16;
+    let funcs = i2c::funcs::I2C_FUNC_10BIT_ADDR;
+    if i2c::is_ten_bit(addr) && !bus.supports(funcs) {
+        return Err(arg_err(
+            "addr",
+            format!("{}", addr).as_str(),
+            &ArgErrorType::Unsupported(funcs),
+        ));
+    }
+    Ok(addr)
+}
+
+// Checks the policy allows identity the access to the addresses and registers
+// (if known) of the bus, and for writes that they aren't disabled or
+// write-protected, before the bus is touched.
+fn check_policy(
+    identity: &str,
+    access: Access,
+    bus: &i2c::BusInfo,
+    addrs: Range,
+    regs: Option<Range>,
+) -> Result<(), ArgError> {
+    let target = Target {
+        bus: bus.id as u32,
+        addrs,
+        regs,
+    };
+    if access == Access::Write {
+        WRITE_PROTECT.check(&target).map_err(ArgError::Forbidden)?;
+    }
+    check_rules(identity, access, &target)
+}
+
+// Checks the policy permits the access, without regard to write protection,
+// for operations which don't touch the devices
+fn check_rules(identity: &str, access: Access, target: &Target) -> Result<(), ArgError> {
+    match &*POLICY {
+        Some(policy) => policy.check(identity, access, target).map_err(ArgError::Forbidden),
+        None => Ok(()),
+    }
+}
+
+// The registers accessed by reading or writing len bytes starting at reg.  As
+// the register pointer of most devices wraps, if this goes beyond the highest
+// register the pointer can address, it could be any register.
+fn pointer_range(reg: &i2c::RegPointer, len: usize) -> Option<Range> {
+    let max = i2c::RegPointer::max(reg.width);
+    match reg.reg.checked_add(std::cmp::max(len, 1) as u32 - 1) {
+        Some(last) if last <= max => Some(Range::new(reg.reg, last)),
+        _ => Some(Range::new(0, max)),
+    }
+}
+
+// As pointer_range, for a single byte register pointer
+fn reg_range(reg: u8, len: usize) -> Option<Range> {
+    pointer_range(&i2c::RegPointer::byte(reg), len)
+}
+
+// Returns the ID of the bus with the given sysfs adapter name, so buses can
+// be addressed by name as well as by ID
+pub(crate) fn bus_id_by_name(name: &str) -> Result<i32, models::I2cBusArg> {
+    let buses = buses();
+    let mut matches = buses
+        .iter()
+        .filter(|bus| bus.name.as_deref() == Some(name));
+    let rc = match (matches.next(), matches.next()) {
+        (Some(bus), None) => Ok(bus.id as i32),
+        (Some(_), Some(_)) => Err(arg_err("busId", name, &ArgErrorType::AmbiguousBus)),
+        (None, _) => Err(arg_err("busId", name, &ArgErrorType::NoSuchBus)),
+    };
+    rc.map_err(|e| match e {
+        ArgError::Error(e) => e,
+        ArgError::Forbidden(_) => unreachable!("bus names aren't subject to the policy"),
+    })
+}
+
 // Construct this function manually
 fn check_arg_values(values: &models::Values) -> Result<Vec<u8>, ArgError> {
     let values = values.clone();
@@ -125,93 +778,984 @@ fn check_arg_values(values: &models::Values) -> Result<Vec<u8>, ArgError> {
     }
 }
 
+// Checks an SMBus word value
+fn check_arg_word(word: i32) -> Result<u16, ArgError> {
+    if (0..=0xffff).contains(&word) {
+        Ok(word as u16)
+    } else {
+        Err(arg_err(
+            "value",
+            format!("{}", word).as_str(),
+            &ArgErrorType::OutOfBounds,
+        ))
+    }
+}
+
+// Checks the values of an SMBus block, at most BLOCK_MAX bytes
+fn check_arg_block(values: &Option<Vec<i32>>) -> Result<Vec<u8>, ArgError> {
+    let values = models::Values {
+        values: values
+            .as_ref()
+            .map(|values| values.iter().map(|x| (*x).into()).collect()),
+    };
+    let values = check_arg_values(&values)?;
+    if values.len() <= i2c::smbus::BLOCK_MAX {
+        Ok(values)
+    } else {
+        Err(arg_err(
+            "values",
+            format!("{:?}", values).as_str(),
+            &ArgErrorType::OutOfBounds,
+        ))
+    }
+}
+
 fn write_byte_reg_check_args(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     reg: &models::Reg,
     value: &models::Value,
-) -> Result<(usize, u16, u8, u8), ArgError> {
-    let bus_id = check_arg_bus_id(&bus_id)?;
-    let addr = check_arg_addr(&addr)?;
-    let reg = check_arg_reg(&reg)?;
+    args: &ext::models::I2cBusRegArgs,
+) -> Result<(Arc<i2c::BusInfo>, u16, i2c::RegPointer, u8), ArgError> {
+    let bus = check_arg_bus_id(&bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, &addr)?;
+    let reg = check_arg_reg_pointer(&reg, args)?;
     let value = check_arg_value(&value)?;
-    Ok((bus_id, addr, reg, value))
+    check_policy(identity, Access::Write, &bus, Range::one(addr.into()), pointer_range(&reg, 1))?;
+    Ok((bus, addr, reg, value))
+}
+
+fn modify_reg_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    modify: &ext::models::I2cBusModify,
+    args: &ext::models::I2cBusRegArgs,
+) -> Result<(Arc<i2c::BusInfo>, u16, i2c::RegPointer, i2c::ModifyOp), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let reg = check_arg_reg_pointer(reg, args)?;
+    let mask = match modify.mask {
+        Some(mask) => check_arg_value(&mask.into()).map_err(|_| {
+            arg_err("mask", format!("{}", mask).as_str(), &ArgErrorType::OutOfBounds)
+        })?,
+        None => return Err(arg_err("mask", "{}", &ArgErrorType::Missing)),
+    };
+    let op = match modify.op.as_deref() {
+        None | Some("update") => i2c::ModifyOp::Update {
+            mask,
+            value: match modify.value {
+                Some(value) => check_arg_value(&value.into())?,
+                None => return Err(arg_err("value", "{}", &ArgErrorType::Missing)),
+            },
+        },
+        Some("set") => i2c::ModifyOp::Set(mask),
+        Some("clear") => i2c::ModifyOp::Clear(mask),
+        Some("toggle") => i2c::ModifyOp::Toggle(mask),
+        Some(op) => return Err(arg_err("op", op, &ArgErrorType::BadOp)),
+    };
+    check_policy(identity, Access::Write, &bus, Range::one(addr.into()), pointer_range(&reg, 1))?;
+    Ok((bus, addr, reg, op))
+}
+
+// The type of an integer value held in a register, of width bytes, and its
+// scaling to engineering units, as raw * scale + offset
+struct ValueType {
+    width: usize,
+    signed: bool,
+    lsb_first: bool,
+    scale: f64,
+    offset: f64,
+}
+
+impl ValueType {
+    fn min(&self) -> i64 {
+        match self.signed {
+            true => -(1 << (8 * self.width - 1)),
+            false => 0,
+        }
+    }
+
+    fn max(&self) -> i64 {
+        match self.signed {
+            true => (1 << (8 * self.width - 1)) - 1,
+            false => (1 << (8 * self.width)) - 1,
+        }
+    }
+
+    // Combines the bytes read into the raw value, sign extending if signed
+    fn decode(&self, bytes: &[u8]) -> i64 {
+        let fold = |value: u64, byte: &u8| (value << 8) | u64::from(*byte);
+        let raw = match self.lsb_first {
+            true => bytes.iter().rev().fold(0, fold),
+            false => bytes.iter().fold(0, fold),
+        };
+        let unused = 64 - 8 * self.width as u32;
+        match self.signed {
+            true => ((raw << unused) as i64) >> unused,
+            false => raw as i64,
+        }
+    }
+
+    fn encode(&self, raw: i64) -> Vec<u8> {
+        let bytes = (0..self.width).map(|i| (raw >> (8 * i)) as u8);
+        match self.lsb_first {
+            true => bytes.collect(),
+            false => bytes.rev().collect(),
+        }
+    }
+
+    fn scaled(&self, raw: i64) -> f64 {
+        raw as f64 * self.scale + self.offset
+    }
+}
+
+// Checks the type, endian, scale and offset of a typed value read or write.
+fn check_arg_value_type(args: &ext::models::I2cBusValueArgs) -> Result<ValueType, ArgError> {
+    let type_ = args.type_.as_deref().unwrap_or("u8");
+    let (signed, width) = match type_ {
+        "u8" => (false, 1),
+        "i8" => (true, 1),
+        "u16" => (false, 2),
+        "i16" => (true, 2),
+        "u24" => (false, 3),
+        "i24" => (true, 3),
+        "u32" => (false, 4),
+        "i32" => (true, 4),
+        _ => return Err(arg_err("type", type_, &ArgErrorType::BadFormat)),
+    };
+    let lsb_first = match args.endian.as_deref() {
+        None | Some("big") => false,
+        Some("little") => true,
+        Some(endian) => return Err(arg_err("endian", endian, &ArgErrorType::BadFormat)),
+    };
+    let scale = args.scale.unwrap_or(1.0);
+    if scale == 0.0 || !scale.is_finite() {
+        return Err(arg_err("scale", format!("{}", scale).as_str(), &ArgErrorType::OutOfBounds));
+    }
+    let offset = args.offset.unwrap_or(0.0);
+    if !offset.is_finite() {
+        return Err(arg_err("offset", format!("{}", offset).as_str(), &ArgErrorType::OutOfBounds));
+    }
+    Ok(ValueType {
+        width,
+        signed,
+        lsb_first,
+        scale,
+        offset,
+    })
+}
+
+fn value_check_args(
+    identity: &str,
+    access: Access,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    args: &ext::models::I2cBusValueArgs,
+) -> Result<(Arc<i2c::BusInfo>, u16, i2c::RegPointer, ValueType), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let reg_args = ext::models::I2cBusRegArgs {
+        reg_width: args.reg_width,
+        reg_endian: args.reg_endian.clone(),
+    };
+    let reg = check_arg_reg_pointer(reg, &reg_args)?;
+    let value_type = check_arg_value_type(args)?;
+    let regs = pointer_range(&reg, value_type.width);
+    check_policy(identity, access, &bus, Range::one(addr.into()), regs)?;
+    Ok((bus, addr, reg, value_type))
+}
+
+// Checks a typed value write, returning the raw value to write, which is the
+// value unscaled and rounded to the nearest integer
+fn write_value_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    write: &ext::models::I2cBusValueWrite,
+    args: &ext::models::I2cBusValueArgs,
+) -> Result<(Arc<i2c::BusInfo>, u16, i2c::RegPointer, ValueType, i64), ArgError> {
+    let (bus, addr, reg, value_type) = value_check_args(identity, Access::Write, bus_id, addr, reg, args)?;
+    let value = match write.value {
+        Some(value) => value,
+        None => return Err(arg_err("value", "{}", &ArgErrorType::Missing)),
+    };
+    let raw = ((value - value_type.offset) / value_type.scale).round();
+    if !(value_type.min() as f64..=value_type.max() as f64).contains(&raw) {
+        return Err(arg_err("value", format!("{}", value).as_str(), &ArgErrorType::OutOfBounds));
+    }
+    Ok((bus, addr, reg, value_type, raw as i64))
+}
+
+// Checks how a chunked transfer of len bytes starting at reg is to be split,
+// and that it doesn't go beyond the highest register the pointer can address.
+fn check_arg_chunking(
+    reg: &i2c::RegPointer,
+    len: usize,
+    write: bool,
+    args: &ext::models::I2cBusChunkedArgs,
+) -> Result<i2c::Chunking, ArgError> {
+    // Writes must leave room after the register pointer for some data
+    let min = if write { reg.width + 1 } else { 1 };
+    let chunk = match args.chunk {
+        None => *MAX_TRANSFER,
+        Some(chunk) if chunk >= min as i32 && chunk as usize <= *MAX_TRANSFER => chunk as usize,
+        Some(chunk) => {
+            return Err(arg_err("chunk", format!("{}", chunk).as_str(), &ArgErrorType::OutOfBounds))
+        }
+    };
+    let page = match args.page {
+        None => None,
+        Some(page) if page > 0 => Some(page as u32),
+        Some(page) => {
+            return Err(arg_err("page", format!("{}", page).as_str(), &ArgErrorType::OutOfBounds))
+        }
+    };
+    let fixed = !args.reg_increment.unwrap_or(true);
+    let last = u64::from(reg.reg) + len as u64 - 1;
+    if !fixed && last > u64::from(i2c::RegPointer::max(reg.width)) {
+        let arg = if write { "values" } else { "numBytes" };
+        return Err(arg_err(arg, format!("{}", len).as_str(), &ArgErrorType::OutOfBounds));
+    }
+    Ok(i2c::Chunking {
+        len: chunk,
+        page,
+        fixed,
+    })
+}
+
+fn chunked_check_args(
+    identity: &str,
+    access: Access,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    len: usize,
+    args: &ext::models::I2cBusChunkedArgs,
+) -> Result<(Arc<i2c::BusInfo>, u16, i2c::RegPointer, i2c::Chunking), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let reg_args = ext::models::I2cBusRegArgs {
+        reg_width: args.reg_width,
+        reg_endian: args.reg_endian.clone(),
+    };
+    let reg = check_arg_reg_pointer(reg, &reg_args)?;
+    let chunking = check_arg_chunking(&reg, len, access == Access::Write, args)?;
+    let regs = pointer_range(&reg, if chunking.fixed { 1 } else { len });
+    check_policy(identity, access, &bus, Range::one(addr.into()), regs)?;
+    Ok((bus, addr, reg, chunking))
+}
+
+// The length of a chunked read.
+fn check_arg_chunked_len(num_bytes: i32) -> Result<usize, ArgError> {
+    match num_bytes {
+        num_bytes if num_bytes >= 1 && num_bytes as usize <= MAX_CHUNKED_LEN => Ok(num_bytes as usize),
+        num_bytes => Err(arg_err("numBytes", format!("{}", num_bytes).as_str(), &ArgErrorType::OutOfBounds)),
+    }
+}
+
+// The values of a chunked write.
+fn check_arg_chunked_values(values: &models::Values) -> Result<Vec<u8>, ArgError> {
+    let values = check_arg_values(values)?;
+    if values.is_empty() {
+        return Err(arg_err("values", "[]", &ArgErrorType::NoValues));
+    }
+    if values.len() > MAX_CHUNKED_LEN {
+        return Err(arg_err("values", format!("{}", values.len()).as_str(), &ArgErrorType::OutOfBounds));
+    }
+    Ok(values)
+}
+
+// Default longest wait for an EEPROM write cycle, which datasheets give as at
+// most 5-10ms, and how often to poll for its completion
+const DEF_EEPROM_WRITE_TIMEOUT_MS: i32 = 50;
+const DEF_EEPROM_POLL_INTERVAL_MS: i32 = 1;
+
+// An EEPROM image format
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImageFormat {
+    Binary,
+    Hex,
+}
+
+// An EEPROM - the bus it's on, its first address, and its type
+struct Eeprom {
+    bus: Arc<i2c::BusInfo>,
+    addr: u16,
+    geometry: &'static eeprom::Geometry,
+}
+
+// Checks an EEPROM's type and address, which must be aligned to the number
+// of addresses it occupies, and the image format
+fn eeprom_check_args(
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    args: &ext::models::I2cBusEepromArgs,
+) -> Result<(Eeprom, ImageFormat), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let geometry = match args.type_.as_deref() {
+        Some(type_) => match eeprom::geometry(type_) {
+            Some(geometry) => geometry,
+            None => return Err(arg_err("type", type_, &ArgErrorType::BadFormat)),
+        },
+        None => return Err(arg_err("type", "{}", &ArgErrorType::Missing)),
+    };
+    if addr & (geometry.blocks() - 1) != 0 || i2c::is_ten_bit(addr) {
+        return Err(arg_err("addr", format!("{}", addr).as_str(), &ArgErrorType::OutOfBounds));
+    }
+    let format = match args.format.as_deref() {
+        None | Some("bin") => ImageFormat::Binary,
+        Some("hex") => ImageFormat::Hex,
+        Some(format) => return Err(arg_err("format", format, &ArgErrorType::BadFormat)),
+    };
+    Ok((Eeprom { bus, addr, geometry }, format))
+}
+
+// Checks that len bytes from offset fit within the EEPROM, and the policy
+// allows the access.  If they're all within one block (slave address), the
+// registers accessed are known.
+fn eeprom_check_range(
+    identity: &str,
+    access: Access,
+    eeprom: &Eeprom,
+    offset: usize,
+    len: usize,
+) -> Result<(), ArgError> {
+    let Eeprom { bus, addr, geometry } = eeprom;
+    if offset >= geometry.size || len == 0 || len > geometry.size - offset {
+        let arg = if access == Access::Write { "image" } else { "length" };
+        return Err(arg_err(arg, format!("{}", len).as_str(), &ArgErrorType::OutOfBounds));
+    }
+    let (first, reg) = geometry.locate(*addr, offset);
+    let (last, _) = geometry.locate(*addr, offset + len - 1);
+    let regs = match first == last {
+        true => pointer_range(&reg, len),
+        false => pointer_range(&reg, geometry.block_size()),
+    };
+    check_policy(identity, access, bus, Range::new(first.into(), last.into()), regs)
+}
+
+fn check_arg_eeprom_offset(offset: Option<i64>) -> Result<usize, ArgError> {
+    match offset.unwrap_or(0) {
+        offset if offset >= 0 && offset <= i64::from(u32::MAX) => Ok(offset as usize),
+        offset => Err(arg_err("offset", format!("{}", offset).as_str(), &ArgErrorType::OutOfBounds)),
+    }
+}
+
+fn read_eeprom_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    args: &ext::models::I2cBusEepromArgs,
+) -> Result<(Eeprom, ImageFormat, usize, usize), ArgError> {
+    let (eeprom, format) = eeprom_check_args(bus_id, addr, args)?;
+    let offset = check_arg_eeprom_offset(args.offset)?;
+    let len = match args.length {
+        Some(len) if len >= 0 => len as usize,
+        Some(len) => return Err(arg_err("length", format!("{}", len).as_str(), &ArgErrorType::OutOfBounds)),
+        None => eeprom.geometry.size.saturating_sub(offset),
+    };
+    eeprom_check_range(identity, Access::Read, &eeprom, offset, len)?;
+    Ok((eeprom, format, offset, len))
+}
+
+// Checks an EEPROM image to program, returning it as runs of contiguous data,
+// and how to program them
+fn write_eeprom_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    image: &[u8],
+    args: &ext::models::I2cBusEepromArgs,
+) -> Result<(Eeprom, eeprom::Image, eeprom::Programming), ArgError> {
+    let (eeprom, format) = eeprom_check_args(bus_id, addr, args)?;
+    let runs = match format {
+        ImageFormat::Binary => vec![(check_arg_eeprom_offset(args.offset)?, image.to_vec())],
+        ImageFormat::Hex => {
+            let text = String::from_utf8_lossy(image);
+            eeprom::parse_hex(&text).map_err(|e| arg_err("image", &e, &ArgErrorType::BadFormat))?
+        }
+    };
+    if runs.iter().all(|(_, data)| data.is_empty()) {
+        return Err(arg_err("image", "{}", &ArgErrorType::NoValues));
+    }
+    for (offset, data) in &runs {
+        eeprom_check_range(identity, Access::Write, &eeprom, *offset, data.len())?;
+    }
+    // Real page sizes are powers of two, so divide the block.  Any other
+    // page would have writes cross a page boundary and wrap around.
+    let page = match args.page {
+        None => eeprom.geometry.page,
+        Some(page)
+            if page > 0
+                && (page as u32).is_power_of_two()
+                && page as usize <= eeprom.geometry.block_size() =>
+        {
+            page as usize
+        }
+        Some(page) => return Err(arg_err("page", format!("{}", page).as_str(), &ArgErrorType::OutOfBounds)),
+    };
+    let timeout = args.write_timeout;
+    let interval = args.poll_interval;
+    let programming = eeprom::Programming {
+        page,
+        max_msg: *MAX_TRANSFER,
+        timeout: check_arg_millis("writeTimeout", timeout, DEF_EEPROM_WRITE_TIMEOUT_MS, 0, 60_000)?,
+        interval: check_arg_millis("pollInterval", interval, DEF_EEPROM_POLL_INTERVAL_MS, 1, 60_000)?,
+    };
+    Ok((eeprom, runs, programming))
 }
 
 fn write_byte_check_args(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     value: &models::Value,
-) -> Result<(usize, u16, u8), ArgError> {
-    let bus_id = check_arg_bus_id(&bus_id)?;
-    let addr = check_arg_addr(&addr)?;
+) -> Result<(Arc<i2c::BusInfo>, u16, u8), ArgError> {
+    let bus = check_arg_bus_id(&bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, &addr)?;
     let value = check_arg_value(&value)?;
-    Ok((bus_id, addr, value))
+    check_policy(identity, Access::Write, &bus, Range::one(addr.into()), None)?;
+    Ok((bus, addr, value))
 }
 
 fn write_bytes_check_args(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     values: &models::Values,
-) -> Result<(usize, u16, Vec<u8>), ArgError> {
-    let bus_id = check_arg_bus_id(&bus_id)?;
-    let addr = check_arg_addr(&addr)?;
+) -> Result<(Arc<i2c::BusInfo>, u16, Vec<u8>), ArgError> {
+    let bus = check_arg_bus_id(&bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, &addr)?;
     let values = check_arg_values(&values)?;
-    Ok((bus_id, addr, values))
+    check_policy(identity, Access::Write, &bus, Range::one(addr.into()), None)?;
+    Ok((bus, addr, values))
 }
 
 fn write_bytes_reg_check_args(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     reg: &models::Reg,
     values: &models::Values,
-) -> Result<(usize, u16, u8, Vec<u8>), ArgError> {
-    let bus_id = check_arg_bus_id(&bus_id)?;
-    let addr = check_arg_addr(&addr)?;
-    let reg = check_arg_reg(&reg)?;
+    args: &ext::models::I2cBusRegArgs,
+) -> Result<(Arc<i2c::BusInfo>, u16, i2c::RegPointer, Vec<u8>), ArgError> {
+    let bus = check_arg_bus_id(&bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, &addr)?;
+    let reg = check_arg_reg_pointer(&reg, args)?;
     let values = check_arg_values(&values)?;
-    Ok((bus_id, addr, reg, values))
+    let regs = pointer_range(&reg, values.len());
+    check_policy(identity, Access::Write, &bus, Range::one(addr.into()), regs)?;
+    Ok((bus, addr, reg, values))
 }
 
 fn read_reg_check_args(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     reg: &models::Reg,
     num_bytes: &models::NumBytes,
-) -> Result<(usize, u16, u8, u8), ArgError> {
-    let bus_id = check_arg_bus_id(&bus_id)?;
-    let addr = check_arg_addr(&addr)?;
-    let reg = check_arg_reg(&reg)?;
+    args: &ext::models::I2cBusRegArgs,
+) -> Result<(Arc<i2c::BusInfo>, u16, i2c::RegPointer, u8), ArgError> {
+    let bus = check_arg_bus_id(&bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, &addr)?;
+    let reg = check_arg_reg_pointer(&reg, args)?;
     let num_bytes = check_arg_num_bytes(&num_bytes)?;
-    Ok((bus_id, addr, reg, num_bytes))
+    let regs = pointer_range(&reg, num_bytes as usize);
+    check_policy(identity, Access::Read, &bus, Range::one(addr.into()), regs)?;
+    Ok((bus, addr, reg, num_bytes))
 }
 
 fn read_byte_check_args(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
-) -> Result<(usize, u16), ArgError> {
-    let bus_id = check_arg_bus_id(&bus_id)?;
-    let addr = check_arg_addr(&addr)?;
-    Ok((bus_id, addr))
+) -> Result<(Arc<i2c::BusInfo>, u16), ArgError> {
+    let bus = check_arg_bus_id(&bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, &addr)?;
+    check_policy(identity, Access::Read, &bus, Range::one(addr.into()), None)?;
+    Ok((bus, addr))
 }
 
 fn read_bytes_check_args(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     num_bytes: &models::NumBytes,
-) -> Result<(usize, u16, u8), ArgError> {
-    let bus_id = check_arg_bus_id(&bus_id)?;
-    let addr = check_arg_addr(&addr)?;
+) -> Result<(Arc<i2c::BusInfo>, u16, u8), ArgError> {
+    let bus = check_arg_bus_id(&bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, &addr)?;
     let num_bytes = check_arg_num_bytes(&num_bytes)?;
-    Ok((bus_id, addr, num_bytes))
+    check_policy(identity, Access::Read, &bus, Range::one(addr.into()), None)?;
+    Ok((bus, addr, num_bytes))
+}
+
+fn scan_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    args: &ext::models::I2cBusScanArgs,
+) -> Result<(Arc<i2c::BusInfo>, u16, u16, i2c::ProbeMode), ArgError> {
+    const DEF_FIRST: i32 = 0x03;
+    const DEF_LAST: i32 = 0x77;
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let first = check_arg_scan_addr(&args.first.unwrap_or(DEF_FIRST).into())?;
+    let last = check_arg_scan_addr(&args.last.unwrap_or(DEF_LAST).into())?;
+    if first > last {
+        return Err(arg_err(
+            "last",
+            format!("{}", last).as_str(),
+            &ArgErrorType::BadRange,
+        ));
+    }
+    let mode = check_arg_probe_mode("mode", args.mode.as_deref())?;
+    check_probe_policy(identity, mode, &bus, Range::new(first.into(), last.into()))?;
+    Ok((bus, first, last, mode))
+}
+
+// Checks the mode to probe for devices with, one of auto (the default), quick
+// or read.
+fn check_arg_probe_mode(arg: &str, mode: Option<&str>) -> Result<i2c::ProbeMode, ArgError> {
+    match mode {
+        None | Some("auto") => Ok(auto_probe_mode()),
+        // Quick probes send a write bit, which some devices act on, so aren't
+        // allowed in read-only mode
+        Some("quick") if WRITE_PROTECT.read_only() => {
+            Err(ArgError::Forbidden(WriteProtect::read_only_violation()))
+        }
+        Some("quick") => Ok(i2c::ProbeMode::Quick),
+        Some("read") => Ok(i2c::ProbeMode::Read),
+        Some(mode) => Err(arg_err(arg, mode, &ArgErrorType::BadMode)),
+    }
+}
+
+// The access probing with mode needs.  Quick probes send a write, as do auto
+// probes at most addresses.
+fn probe_access(mode: i2c::ProbeMode) -> Access {
+    match mode {
+        i2c::ProbeMode::Read => Access::Read,
+        i2c::ProbeMode::Auto | i2c::ProbeMode::Quick => Access::Write,
+    }
+}
+
+// Checks identity may probe the addresses of the bus with mode.  As auto
+// probes read rather than write at write-protected addresses, they only need
+// the policy to allow writes.
+fn check_probe_policy(identity: &str, mode: i2c::ProbeMode, bus: &i2c::BusInfo, addrs: Range) -> Result<(), ArgError> {
+    match mode {
+        i2c::ProbeMode::Auto => {
+            let target = Target {
+                bus: bus.id as u32,
+                addrs,
+                regs: None,
+            };
+            check_rules(identity, Access::Write, &target)
+        }
+        mode => check_policy(identity, probe_access(mode), bus, addrs, None),
+    }
+}
+
+// The mode to probe addr with, falling back from auto to read probes where
+// the address is write-protected
+fn probe_mode(bus: &i2c::BusInfo, addr: u16, mode: i2c::ProbeMode) -> i2c::ProbeMode {
+    let target = Target {
+        bus: bus.id as u32,
+        addrs: Range::one(addr.into()),
+        regs: None,
+    };
+    match mode {
+        i2c::ProbeMode::Auto if WRITE_PROTECT.check(&target).is_err() => i2c::ProbeMode::Read,
+        mode => mode,
+    }
+}
+
+// The mode to probe devices with when none is given.  Auto probes use quick
+// writes at most addresses, so in read-only mode reads are used instead.
+fn auto_probe_mode() -> i2c::ProbeMode {
+    match WRITE_PROTECT.read_only() {
+        true => i2c::ProbeMode::Read,
+        false => i2c::ProbeMode::Auto,
+    }
+}
+
+// Default longest to wait for a device to ACK, and how often to probe it
+const DEF_WAIT_TIMEOUT_MS: i32 = 100;
+const DEF_WAIT_INTERVAL_MS: i32 = 1;
+
+// Checks how to wait for a device to ACK, with arg prefixing the names of
+// invalid arguments.  The timeout can't exceed the request timeout, and the
+// interval must be at least 1ms.
+fn check_arg_ack_wait(arg: &str, wait: &ext::models::I2cBusWait) -> Result<i2c::AckWait, ArgError> {
+    let mode = check_arg_probe_mode(&format!("{}mode", arg), wait.mode.as_deref())?;
+    let max = TIMEOUT.as_millis() as i32;
+    let timeout = std::cmp::min(DEF_WAIT_TIMEOUT_MS, max);
+    Ok(i2c::AckWait {
+        mode,
+        timeout: check_arg_millis(&format!("{}timeout", arg), wait.timeout, timeout, 0, max)?,
+        interval: check_arg_millis(&format!("{}interval", arg), wait.interval, DEF_WAIT_INTERVAL_MS, 1, max)?,
+    })
+}
+
+// Checks a duration in milliseconds, or the default if not given, is within
+// min..=max.  Intervals between polls of a device have a min of 1, as polling
+// without an interval would spin on the bus while holding it.
+fn check_arg_millis(arg: &str, value: Option<i32>, default: i32, min: i32, max: i32) -> Result<Duration, ArgError> {
+    match value.unwrap_or(default) {
+        ms if (min..=max).contains(&ms) => Ok(Duration::from_millis(ms as u64)),
+        ms => Err(arg_err(arg, format!("{}", ms).as_str(), &ArgErrorType::OutOfBounds)),
+    }
+}
+
+fn wait_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    wait: &ext::models::I2cBusWait,
+) -> Result<(Arc<i2c::BusInfo>, u16, i2c::AckWait), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let mut wait = check_arg_ack_wait("", wait)?;
+    check_probe_policy(identity, wait.mode, &bus, Range::one(addr.into()))?;
+    wait.mode = probe_mode(&bus, addr, wait.mode);
+    Ok((bus, addr, wait))
+}
+
+// Bus, addr, first and last registers, mode and whether to format as text
+type DumpArgs = (Arc<i2c::BusInfo>, u16, u8, u8, i2c::DumpMode, bool);
+
+fn dump_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    args: &ext::models::I2cBusDumpArgs,
+) -> Result<DumpArgs, ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let first = check_arg_reg(&args.first.unwrap_or(0).into())?;
+    let last = check_arg_reg(&args.last.unwrap_or(255).into())?;
+    if first > last {
+        return Err(arg_err(
+            "last",
+            format!("{}", last).as_str(),
+            &ArgErrorType::BadRange,
+        ));
+    }
+    let mode = match args.mode.as_deref() {
+        None | Some("byte") => i2c::DumpMode::Byte,
+        Some("word") => i2c::DumpMode::Word,
+        Some("block") => i2c::DumpMode::Block,
+        Some(mode) => return Err(arg_err("mode", mode, &ArgErrorType::BadMode)),
+    };
+    let text = match args.format.as_deref() {
+        None | Some("json") => false,
+        Some("text") => true,
+        Some(format) => return Err(arg_err("format", format, &ArgErrorType::BadFormat)),
+    };
+    let regs = Some(Range::new(first.into(), last.into()));
+    check_policy(identity, Access::Read, &bus, Range::one(addr.into()), regs)?;
+    Ok((bus, addr, first, last, mode, text))
+}
+
+fn transaction_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    txn: &ext::models::I2cBusTransaction,
+) -> Result<(Arc<i2c::BusInfo>, Vec<i2c::Segment>), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let segments = match &txn.segments {
+        Some(segments) if !segments.is_empty() && segments.len() <= i2c::MAX_RDWR_MSGS => {
+            segments
+        }
+        Some(segments) => {
+            return Err(arg_err(
+                "segments",
+                format!("{}", segments.len()).as_str(),
+                &ArgErrorType::OutOfBounds,
+            ))
+        }
+        None => return Err(arg_err("segments", "{}", &ArgErrorType::NoValues)),
+    };
+    let mut rc = Vec::<i2c::Segment>::with_capacity(segments.len());
+    for (ii, segment) in segments.iter().enumerate() {
+        let arg = format!("segments[{}]", ii);
+        let val = format!("{:?}", segment);
+        let addr = match segment.addr {
+            Some(addr) => check_arg_addr(&bus, &addr.into())?,
+            None => return Err(arg_err(&arg, &val, &ArgErrorType::BadSegment)),
+        };
+        let (read, data, wait) = match (&segment.write, segment.read, &segment.wait) {
+            (Some(values), None, None) => {
+                let values = models::Values {
+                    values: Some(values.iter().map(|x| (*x).into()).collect()),
+                };
+                (false, check_arg_values(&values)?, None)
+            }
+            (None, Some(num_bytes), None) => {
+                let num_bytes = check_arg_num_bytes(&num_bytes.into())?;
+                (true, vec![0; num_bytes as usize], None)
+            }
+            (None, None, Some(wait)) => {
+                let mut wait = check_arg_ack_wait(&format!("{}.wait.", arg), wait)?;
+                check_probe_policy(identity, wait.mode, &bus, Range::one(addr.into()))?;
+                wait.mode = probe_mode(&bus, addr, wait.mode);
+                (false, vec![], Some(wait))
+            }
+            _ => return Err(arg_err(&arg, &val, &ArgErrorType::BadSegment)),
+        };
+        if wait.is_none() {
+            let access = if read { Access::Read } else { Access::Write };
+            check_policy(identity, access, &bus, Range::one(addr.into()), None)?;
+        }
+        rc.push(i2c::Segment { addr, read, data, wait });
+    }
+    Ok((bus, rc))
+}
+
+fn smbus_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    op: &ext::models::I2cBusSmbus,
+) -> Result<(Arc<i2c::BusInfo>, u16, SmbusOp), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let command = || match op.command {
+        Some(command) => check_arg_reg(&command.into()),
+        None => Err(arg_err("command", "{}", &ArgErrorType::Missing)),
+    };
+    let value = || match op.value {
+        Some(value) => Ok(value),
+        None => Err(arg_err("value", "{}", &ArgErrorType::Missing)),
+    };
+    let byte = || check_arg_value(&value()?.into());
+    let word = || check_arg_word(value()?);
+    let op = match op.op.as_deref() {
+        Some("quick") => SmbusOp::Quick(op.value.unwrap_or(0) != 0),
+        Some("receive_byte") => SmbusOp::ReceiveByte,
+        Some("send_byte") => SmbusOp::SendByte(byte()?),
+        Some("read_byte_data") => SmbusOp::ReadByteData(command()?),
+        Some("write_byte_data") => SmbusOp::WriteByteData(command()?, byte()?),
+        Some("read_word_data") => SmbusOp::ReadWordData(command()?),
+        Some("write_word_data") => SmbusOp::WriteWordData(command()?, word()?),
+        Some("process_call") => SmbusOp::ProcessCall(command()?, word()?),
+        Some("block_read") => SmbusOp::BlockRead(command()?),
+        Some("block_write") => SmbusOp::BlockWrite(command()?, check_arg_block(&op.values)?),
+        Some("block_process_call") => {
+            SmbusOp::BlockProcessCall(command()?, check_arg_block(&op.values)?)
+        }
+        Some(name) => return Err(arg_err("op", name, &ArgErrorType::BadOp)),
+        None => return Err(arg_err("op", "{}", &ArgErrorType::Missing)),
+    };
+    check_bus_funcs(&bus, op.funcs())?;
+    let (access, regs) = smbus_access(&op);
+    check_policy(identity, access, &bus, Range::one(addr.into()), regs)?;
+    Ok((bus, addr, op))
+}
+
+// The access an SMBus operation needs, and the registers (commands) it
+// accesses.  Operations without a command are raw.
+fn smbus_access(op: &SmbusOp) -> (Access, Option<Range>) {
+    match op {
+        SmbusOp::Quick(true) | SmbusOp::ReceiveByte => (Access::Read, None),
+        SmbusOp::Quick(false) | SmbusOp::SendByte(_) => (Access::Write, None),
+        SmbusOp::ReadByteData(reg) | SmbusOp::BlockRead(reg) => (Access::Read, reg_range(*reg, 1)),
+        SmbusOp::ReadWordData(reg) => (Access::Read, reg_range(*reg, 2)),
+        SmbusOp::WriteByteData(reg, _)
+        | SmbusOp::BlockWrite(reg, _)
+        | SmbusOp::BlockProcessCall(reg, _) => (Access::Write, reg_range(*reg, 1)),
+        SmbusOp::WriteWordData(reg, _) | SmbusOp::ProcessCall(reg, _) => {
+            (Access::Write, reg_range(*reg, 2))
+        }
+    }
+}
+
+// Checks access to a device's profile binding.  Binding or unbinding changes
+// how the device's named registers are accessed by every client, so needs
+// write access to the whole device, but doesn't touch the device itself so
+// isn't subject to write protection.
+fn device_check_args(
+    identity: &str,
+    access: Access,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+) -> Result<(Arc<i2c::BusInfo>, u16), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let target = Target {
+        bus: bus.id as u32,
+        addrs: Range::one(addr.into()),
+        regs: None,
+    };
+    check_rules(identity, access, &target)?;
+    Ok((bus, addr))
+}
+
+// Checks identity may rescan for buses.  A rescan changes the buses every
+// client sees, removing any which have gone, so needs write access to every
+// device on every bus, but doesn't touch the devices so, as for bindings,
+// isn't subject to write protection or read-only mode.
+fn rescan_check_args(identity: &str) -> Result<(), ArgError> {
+    let addrs = Range::new(0, u32::from(i2c::ADDR_TEN_BIT) + 0x3ff);
+    for bus in buses().iter() {
+        let target = Target {
+            bus: bus.id as u32,
+            addrs,
+            regs: None,
+        };
+        check_rules(identity, Access::Write, &target)?;
+    }
+    Ok(())
+}
+
+fn bind_device_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    device: &ext::models::I2cBusDevice,
+) -> Result<(Arc<i2c::BusInfo>, u16, String), ArgError> {
+    let (bus, addr) = device_check_args(identity, Access::Write, bus_id, addr)?;
+    match device.profile.as_deref() {
+        Some(name) if PROFILES.contains_key(name) => Ok((bus, addr, name.to_string())),
+        Some(name) => Err(arg_err("profile", name, &ArgErrorType::NoSuchProfile)),
+        None => Err(arg_err("profile", "null", &ArgErrorType::Missing)),
+    }
+}
+
+// Checks access to a named register of a device, returning the register from
+// the device's profile and the register pointer to send for it
+fn named_reg_check_args(
+    identity: &str,
+    access: Access,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    name: &str,
+) -> Result<(Arc<i2c::BusInfo>, u16, Register, i2c::RegPointer), ArgError> {
+    let bus = check_arg_bus_id(bus_id)?;
+    check_bus_funcs(&bus, i2c::funcs::I2C_FUNC_I2C)?;
+    let addr = check_arg_addr(&bus, addr)?;
+    let profile = match DEVICES.lock().unwrap().get(&(bus.id, addr)) {
+        Some(profile) => profile.clone(),
+        None => {
+            return Err(arg_err(
+                "addr",
+                format!("{}", addr).as_str(),
+                &ArgErrorType::NoProfile,
+            ))
+        }
+    };
+    let profile = &PROFILES[&profile];
+    let register = match profile.register(name) {
+        Some(register) => register.clone(),
+        None => return Err(arg_err("name", name, &ArgErrorType::NoSuchRegister)),
+    };
+    match (access, register.access) {
+        (Access::Read, RegAccess::WriteOnly) => {
+            return Err(arg_err("name", name, &ArgErrorType::WriteOnly))
+        }
+        (Access::Write, RegAccess::ReadOnly) => {
+            return Err(arg_err("name", name, &ArgErrorType::ReadOnly))
+        }
+        _ => (),
+    }
+    let pointer = i2c::RegPointer {
+        reg: register.reg,
+        width: profile.pointer_width as usize,
+        lsb_first: profile.pointer_endian == profile::Endian::Little,
+    };
+    let regs = pointer_range(&pointer, register.width as usize);
+    check_policy(identity, access, &bus, Range::one(addr.into()), regs)?;
+    Ok((bus, addr, register, pointer))
+}
+
+// A validated write to a named register - a value for the whole register,
+// and/or values for some of its fields.  Without a value, the fields are
+// written into the register's current value.
+struct NamedRegWrite {
+    value: Option<u32>,
+    fields: Vec<(profile::Field, u32)>,
+}
+
+impl NamedRegWrite {
+    fn apply(&self, current: u32) -> u32 {
+        let value = self.value.unwrap_or(current);
+        self.fields.iter().fold(value, |value, (field, x)| field.set(value, *x))
+    }
+}
+
+fn write_named_reg_check_args(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    name: &str,
+    write: &ext::models::I2cBusNamedRegWrite,
+) -> Result<(Arc<i2c::BusInfo>, u16, Register, i2c::RegPointer, NamedRegWrite), ArgError> {
+    let (bus, addr, register, pointer) = named_reg_check_args(identity, Access::Write, bus_id, addr, name)?;
+    let value = match write.value {
+        Some(value) if value < 0 || value > i64::from(register.max()) => {
+            return Err(arg_err(
+                "value",
+                format!("{}", value).as_str(),
+                &ArgErrorType::OutOfBounds,
+            ))
+        }
+        value => value.map(|value| value as u32),
+    };
+    let mut fields = Vec::new();
+    for (name, value) in write.fields.iter().flatten() {
+        let field = match register.field(name) {
+            Some(field) => field,
+            None => return Err(arg_err("fields", name, &ArgErrorType::NoSuchField)),
+        };
+        if *value < 0 || *value > i64::from(field.max()) {
+            return Err(arg_err(
+                &format!("fields.{}", name),
+                format!("{}", value).as_str(),
+                &ArgErrorType::OutOfBounds,
+            ));
+        }
+        fields.push((field.clone(), *value as u32));
+    }
+    // Fields alone are written into the current value, which can't be read
+    // from a write-only register
+    if value.is_none() && (fields.is_empty() || register.access == RegAccess::WriteOnly) {
+        return Err(arg_err("value", "null", &ArgErrorType::Missing));
+    }
+    Ok((bus, addr, register, pointer, NamedRegWrite { value, fields }))
 }
 
 macro_rules! impl_from_arg_error {
     ($type:tt) => {
         impl From<ArgError> for $type {
             fn from(e: ArgError) -> Self {
-                let ArgError::Error(e) = e;
-                $type::BadRequest(e)
+                match e {
+                    ArgError::Error(e) => $type::BadRequest(e),
+                    ArgError::Forbidden(violation) => {
+                        $type::Forbidden(ext::models::I2cBusForbidden {
+                            error: Some(violation.error),
+                            description: Some(violation.description),
+                            rule: violation.rule,
+                        })
+                    }
+                }
             }
         }
     };
@@ -225,6 +1769,44 @@ impl_from_arg_error!(I2cBusReadRegResponse);
 impl_from_arg_error!(I2cBusReadByteResponse);
 impl_from_arg_error!(I2cBusReadBytesResponse);
 
+// Bus info isn't subject to the policy, so can't be forbidden
+impl From<ArgError> for I2cBusInfoResponse {
+    fn from(e: ArgError) -> Self {
+        match e {
+            ArgError::Error(e) => I2cBusInfoResponse::BadRequest(e),
+            ArgError::Forbidden(_) => unreachable!("bus info isn't subject to the policy"),
+        }
+    }
+}
+
+impl From<ArgError> for ext::I2cBusRescanResponse {
+    fn from(e: ArgError) -> Self {
+        match e {
+            ArgError::Error(_) => unreachable!("rescan takes no arguments"),
+            ArgError::Forbidden(violation) => {
+                ext::I2cBusRescanResponse::Forbidden(ext::models::I2cBusForbidden {
+                    error: Some(violation.error),
+                    description: Some(violation.description),
+                    rule: violation.rule,
+                })
+            }
+        }
+    }
+}
+
+impl_from_arg_error!(I2cBusScanResponse);
+impl_from_arg_error!(I2cBusDumpResponse);
+impl_from_arg_error!(I2cBusTransactionResponse);
+impl_from_arg_error!(I2cBusSmbusResponse);
+impl_from_arg_error!(I2cBusModifyResponse);
+impl_from_arg_error!(I2cBusWaitResponse);
+impl_from_arg_error!(I2cBusValueResponse);
+impl_from_arg_error!(I2cBusChunkedResponse);
+impl_from_arg_error!(I2cBusEepromReadResponse);
+impl_from_arg_error!(I2cBusEepromWriteResponse);
+impl_from_arg_error!(I2cBusDeviceResponse);
+impl_from_arg_error!(I2cBusNamedRegResponse);
+
 macro_rules! unwrap_or_return_rsp {
     ($fn:tt, $exp:expr) => {
         match $exp {
@@ -272,6 +1854,86 @@ macro_rules! impl_from_i2c_bus_error {
     };
 }
 
+// Returns the errno a failed bus operation reported, if any
+fn errno(e: &i2c::BusError) -> Option<i32> {
+    match e {
+        i2c::BusError::LinuxI2CError(i2cdev2::linux::LinuxI2CError::Nix(nix::Error::Sys(e))) => {
+            Some(*e as i32)
+        }
+        i2c::BusError::LinuxI2CError(i2cdev2::linux::LinuxI2CError::Nix(_)) => None,
+        i2c::BusError::LinuxI2CError(i2cdev2::linux::LinuxI2CError::Io(e)) => e.raw_os_error(),
+        i2c::BusError::Io(e) => e.raw_os_error(),
+    }
+}
+
+// Locks the bus for an operation, recording in the metrics how long that took.
+// The returned timer records the operation itself.
+fn lock<'a>(
+    bus: &'a i2c::BusInfo,
+    addr: Option<u16>,
+    op: &'static str,
+) -> (i2c::Bus<'a>, metrics::Timer) {
+    let mut timer = metrics::Timer::start(bus.id, addr, op);
+    let bus = bus.lock();
+    timer.locked();
+    (bus, timer)
+}
+
+// Records the outcome of an operation in the metrics, with the numbers of
+// bytes it read and wrote if it succeeded
+fn record<T>(timer: metrics::Timer, result: &Result<T, i2c::BusError>, read: usize, written: usize) {
+    match result {
+        Ok(_) => timer.ok(read, written),
+        Err(e) => timer.failed(errno(e)),
+    }
+}
+
+// Describes a chunked transfer which failed part way through, with the
+// values read before the failure, for a read
+fn chunked_error(e: &i2c::ChunkError, values: Option<&[u8]>) -> ext::models::I2cBusChunkedError {
+    let error = errno(&e.error);
+    ext::models::I2cBusChunkedError {
+        error,
+        description: Some(match error {
+            Some(error) => format!("{:?}", nix::errno::Errno::from_i32(error)),
+            None => format!("{:?}", e.error),
+        }),
+        offset: Some(e.offset as i64),
+        values: values.map(|values| values.iter().map(|x| i32::from(*x)).collect()),
+    }
+}
+
+// The address all of a transaction's segments are to, if they share one
+fn segments_addr(segments: &[i2c::Segment]) -> Option<u16> {
+    let addr = segments.first()?.addr;
+    match segments.iter().all(|s| s.addr == addr) {
+        true => Some(addr),
+        false => None,
+    }
+}
+
+// The numbers of bytes an SMBus operation read and wrote, given the data it
+// returned, if it succeeded
+fn smbus_bytes(op: &SmbusOp, data: Option<&SmbusData>) -> (usize, usize) {
+    let read = match data {
+        Some(SmbusData::Byte(_)) => 1,
+        Some(SmbusData::Word(_)) => 2,
+        Some(SmbusData::Block(values)) => values.len(),
+        Some(SmbusData::None) | None => 0,
+    };
+    let written = match op {
+        SmbusOp::Quick(_) | SmbusOp::ReceiveByte => 0,
+        SmbusOp::SendByte(_)
+        | SmbusOp::ReadByteData(_)
+        | SmbusOp::ReadWordData(_)
+        | SmbusOp::BlockRead(_) => 1,
+        SmbusOp::WriteByteData(_, _) => 2,
+        SmbusOp::WriteWordData(_, _) | SmbusOp::ProcessCall(_, _) => 3,
+        SmbusOp::BlockWrite(_, values) | SmbusOp::BlockProcessCall(_, values) => 1 + values.len(),
+    };
+    (read, written)
+}
+
 impl_from_i2c_bus_error!(I2cBusWriteByteRegResponse);
 impl_from_i2c_bus_error!(I2cBusWriteBytesRegResponse);
 impl_from_i2c_bus_error!(I2cBusWriteByteResponse);
@@ -279,17 +1941,27 @@ impl_from_i2c_bus_error!(I2cBusWriteBytesResponse);
 impl_from_i2c_bus_error!(I2cBusReadRegResponse);
 impl_from_i2c_bus_error!(I2cBusReadByteResponse);
 impl_from_i2c_bus_error!(I2cBusReadBytesResponse);
+impl_from_i2c_bus_error!(I2cBusDumpResponse);
+impl_from_i2c_bus_error!(I2cBusTransactionResponse);
+impl_from_i2c_bus_error!(I2cBusSmbusResponse);
+impl_from_i2c_bus_error!(I2cBusModifyResponse);
+impl_from_i2c_bus_error!(I2cBusWaitResponse);
+impl_from_i2c_bus_error!(I2cBusValueResponse);
+impl_from_i2c_bus_error!(I2cBusNamedRegResponse);
 
 pub(crate) fn write_byte(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     value: &models::Value,
 ) -> I2cBusWriteByteResponse {
     info!("API {} : {:?} {:?} {:?}", "write_byte", bus_id, addr, value);
-    let (bus_id, addr, value) =
-        unwrap_or_return_rsp!(write_byte, write_byte_check_args(&bus_id, &addr, &value));
-    let mut buses = BUSES.lock().unwrap();
-    let rsp = match buses[bus_id].write_byte(addr, value) {
+    let (bus, addr, value) =
+        unwrap_or_return_rsp!(write_byte, write_byte_check_args(identity, &bus_id, &addr, &value));
+    let (mut bus, timer) = lock(&bus, Some(addr), "write_byte");
+    let result = bus.write_byte(addr, value);
+    record(timer, &result, 0, 1);
+    let rsp = match result {
         Ok(rc) => I2cBusWriteByteResponse::OK(models::I2cBusOk { ok: Some(rc) }),
         Err(e) => e.into(),
     };
@@ -298,15 +1970,18 @@ pub(crate) fn write_byte(
 }
 
 pub(crate) fn write_bytes(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     values: &models::Values,
 ) -> I2cBusWriteBytesResponse {
     info!("API {} : {:?} {:?} {:?}", "write_bytes", bus_id, addr, values);
-    let (bus_id, addr, mut values) =
-        unwrap_or_return_rsp!(write_bytes, write_bytes_check_args(&bus_id, &addr, &values));
-    let mut buses = BUSES.lock().unwrap();
-    let rsp = match buses[bus_id].write_bytes(addr, &mut values) {
+    let (bus, addr, mut values) =
+        unwrap_or_return_rsp!(write_bytes, write_bytes_check_args(identity, &bus_id, &addr, &values));
+    let (mut bus, timer) = lock(&bus, Some(addr), "write_bytes");
+    let result = bus.write_bytes(addr, &mut values);
+    record(timer, &result, 0, values.len());
+    let rsp = match result {
         Ok(rc) => I2cBusWriteBytesResponse::OK(models::I2cBusOk { ok: Some(rc) }),
         Err(e) => e.into(),
     };
@@ -315,17 +1990,20 @@ pub(crate) fn write_bytes(
 }
 
 pub(crate) fn write_bytes_reg(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     reg: &models::Reg,
     values: &models::Values,
+    args: &ext::models::I2cBusRegArgs,
 ) -> I2cBusWriteBytesRegResponse {
-    info!("API {} : {:?} {:?} {:?} {:?}", "write_bytes_reg", bus_id, addr, reg, values);
-    let (bus_id, addr, reg, mut values) =
-        unwrap_or_return_rsp!(write_bytes_reg, write_bytes_reg_check_args(&bus_id, &addr, &reg, &values));
-    values.insert(0, reg);
-    let mut buses = BUSES.lock().unwrap();
-    let rsp = match buses[bus_id].write_bytes(addr, &mut values) {
+    info!("API {} : {:?} {:?} {:?} {:?} {:?}", "write_bytes_reg", bus_id, addr, reg, values, args);
+    let (bus, addr, reg, values) =
+        unwrap_or_return_rsp!(write_bytes_reg, write_bytes_reg_check_args(identity, &bus_id, &addr, &reg, &values, args));
+    let (mut bus, timer) = lock(&bus, Some(addr), "write_bytes_reg");
+    let result = bus.write_reg_bytes(addr, reg, &values);
+    record(timer, &result, 0, reg.width + values.len());
+    let rsp = match result {
         Ok(rc) => I2cBusWriteBytesRegResponse::OK(models::I2cBusOk { ok: Some(rc) }),
         Err(e) => e.into(),
     };
@@ -334,16 +2012,20 @@ pub(crate) fn write_bytes_reg(
 }
 
 pub(crate) fn write_byte_reg(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     reg: &models::Reg,
     value: &models::Value,
+    args: &ext::models::I2cBusRegArgs,
 ) -> I2cBusWriteByteRegResponse {
-    info!("API {} : {:?} {:?} {:?} {:?}", "write_byte_reg", bus_id, addr, reg, value);
-    let (bus_id, addr, reg, value) =
-        unwrap_or_return_rsp!(write_byte_reg, write_byte_reg_check_args(&bus_id, &addr, &reg, &value));
-    let mut buses = BUSES.lock().unwrap();
-    let rsp = match buses[bus_id].write_reg(addr, reg, value) {
+    info!("API {} : {:?} {:?} {:?} {:?} {:?}", "write_byte_reg", bus_id, addr, reg, value, args);
+    let (bus, addr, reg, value) =
+        unwrap_or_return_rsp!(write_byte_reg, write_byte_reg_check_args(identity, &bus_id, &addr, &reg, &value, args));
+    let (mut bus, timer) = lock(&bus, Some(addr), "write_byte_reg");
+    let result = bus.write_reg(addr, reg, value);
+    record(timer, &result, 0, reg.width + 1);
+    let rsp = match result {
         Ok(rc) => I2cBusWriteByteRegResponse::OK(models::I2cBusOk { ok: Some(rc) }),
         Err(e) => e.into(),
     };
@@ -351,12 +2033,280 @@ pub(crate) fn write_byte_reg(
     rsp
 }
 
-pub(crate) fn read_byte(bus_id: &models::BusId, addr: &models::Addr) -> I2cBusReadByteResponse {
+// Modifies bits of a register, reading and writing it while holding the bus
+// lock, so other requests can't change it in between
+pub(crate) fn modify_reg(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    modify: &ext::models::I2cBusModify,
+    args: &ext::models::I2cBusRegArgs,
+) -> I2cBusModifyResponse {
+    info!("API {} : {:?} {:?} {:?} {:?} {:?}", "modify_reg", bus_id, addr, reg, modify, args);
+    let (bus, addr, reg, op) =
+        unwrap_or_return_rsp!(modify_reg, modify_reg_check_args(identity, bus_id, addr, reg, modify, args));
+    let (mut bus, timer) = lock(&bus, Some(addr), "modify_reg");
+    let result = bus.modify_reg(addr, reg, op);
+    record(timer, &result, 1, 2 * reg.width + 1);
+    let rsp = match result {
+        Ok((before, after)) => I2cBusModifyResponse::OK(ext::models::I2cBusModifyResult {
+            before: Some(i32::from(before)),
+            after: Some(i32::from(after)),
+        }),
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "modify_reg", rsp);
+    rsp
+}
+
+pub(crate) fn eeprom_types() -> I2cBusEepromTypesResponse {
+    I2cBusEepromTypesResponse::OK(
+        eeprom::GEOMETRIES
+            .iter()
+            .map(|geometry| ext::models::I2cBusEepromType {
+                name: Some(geometry.name.to_string()),
+                size: Some(geometry.size as i64),
+                page: Some(geometry.page as i32),
+                pointer_width: Some(geometry.pointer_width as i32),
+                addresses: Some(i32::from(geometry.blocks())),
+            })
+            .collect(),
+    )
+}
+
+// Downloads an EEPROM's contents, or part of them, as a binary or Intel HEX
+// image
+pub(crate) fn read_eeprom(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    args: &ext::models::I2cBusEepromArgs,
+) -> I2cBusEepromReadResponse {
+    info!("API {} : {:?} {:?} {:?}", "read_eeprom", bus_id, addr, args);
+    let (eeprom, format, offset, len) =
+        unwrap_or_return_rsp!(read_eeprom, read_eeprom_check_args(identity, bus_id, addr, args));
+    let (mut bus, timer) = lock(&eeprom.bus, Some(eeprom.addr), "read_eeprom");
+    let mut values: Vec<u8> = vec![0; len];
+    let result = eeprom::read(&mut bus, eeprom.addr, eeprom.geometry, offset, &mut values, *MAX_TRANSFER);
+    let rsp = match result {
+        Ok(()) => {
+            timer.ok(len, 0);
+            match format {
+                ImageFormat::Binary => I2cBusEepromReadResponse::OK(values),
+                ImageFormat::Hex => I2cBusEepromReadResponse::OKText(eeprom::format_hex(offset, &values)),
+            }
+        }
+        Err(e) => {
+            timer.failed(errno(&e.error));
+            let read = &values[..e.offset - offset];
+            I2cBusEepromReadResponse::TransactionFailed(chunked_error(&e, Some(read)))
+        }
+    };
+    match &rsp {
+        I2cBusEepromReadResponse::OK(_) | I2cBusEepromReadResponse::OKText(_) => {
+            info!("API {} -> {} bytes", "read_eeprom", len)
+        }
+        I2cBusEepromReadResponse::TransactionFailed(e) => info!(
+            "API {} -> {:?} at offset {:?}",
+            "read_eeprom", e.description, e.offset
+        ),
+        rsp => info!("API {} -> {:?}", "read_eeprom", rsp),
+    }
+    rsp
+}
+
+// Programs an EEPROM with a binary or Intel HEX image, a page at a time,
+// waiting for each page's write cycle by ACK polling, and then by default
+// reads it back to verify it
+pub(crate) fn write_eeprom(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    image: &[u8],
+    args: &ext::models::I2cBusEepromArgs,
+) -> I2cBusEepromWriteResponse {
+    info!("API {} : {:?} {:?} {} bytes {:?}", "write_eeprom", bus_id, addr, image.len(), args);
+    let (eeprom, runs, programming) =
+        unwrap_or_return_rsp!(write_eeprom, write_eeprom_check_args(identity, bus_id, addr, image, args));
+    let verify = args.verify.unwrap_or(true);
+    let (mut bus, timer) = lock(&eeprom.bus, Some(eeprom.addr), "write_eeprom");
+    let mut writes = 0;
+    let mut result = Ok(());
+    for (offset, data) in &runs {
+        result = eeprom::write(&mut bus, eeprom.addr, eeprom.geometry, *offset, data, programming)
+            .map(|count| writes += count);
+        if result.is_err() {
+            break;
+        }
+    }
+    if verify && result.is_ok() {
+        result = runs.iter().try_for_each(|(offset, data)| {
+            eeprom::verify(&mut bus, eeprom.addr, eeprom.geometry, *offset, data, *MAX_TRANSFER)
+        });
+    }
+    let bytes: usize = runs.iter().map(|(_, data)| data.len()).sum();
+    let rsp = match result {
+        Ok(()) => {
+            let read = if verify { bytes } else { 0 };
+            timer.ok(read, bytes + writes * eeprom.geometry.pointer_width);
+            I2cBusEepromWriteResponse::OK(ext::models::I2cBusEepromWritten {
+                bytes: Some(bytes as i64),
+                writes: Some(writes as i32),
+                verified: Some(verify),
+            })
+        }
+        Err(e) => {
+            timer.failed(errno(&e.error));
+            I2cBusEepromWriteResponse::TransactionFailed(chunked_error(&e, None))
+        }
+    };
+    info!("API {} -> {:?}", "write_eeprom", rsp);
+    rsp
+}
+
+// Reads num_bytes starting at reg, split into as many transfers as needed
+pub(crate) fn read_chunked(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    num_bytes: i32,
+    args: &ext::models::I2cBusChunkedArgs,
+) -> I2cBusChunkedResponse {
+    info!("API {} : {:?} {:?} {:?} {:?} {:?}", "read_chunked", bus_id, addr, reg, num_bytes, args);
+    let len = unwrap_or_return_rsp!(read_chunked, check_arg_chunked_len(num_bytes));
+    let (bus, addr, reg, chunking) = unwrap_or_return_rsp!(
+        read_chunked,
+        chunked_check_args(identity, Access::Read, bus_id, addr, reg, len, args)
+    );
+    let (mut bus, timer) = lock(&bus, Some(addr), "read_chunked");
+    let mut values: Vec<u8> = vec![0; len];
+    let rsp = match bus.read_reg_chunked(addr, reg, &mut values, chunking) {
+        Ok(chunks) => {
+            timer.ok(len, chunks * reg.width);
+            I2cBusChunkedResponse::OK(ext::models::I2cBusChunked {
+                chunks: Some(chunks as i32),
+                values: Some(values.iter().map(|x| i32::from(*x)).collect()),
+            })
+        }
+        Err(e) => {
+            timer.failed(errno(&e.error));
+            I2cBusChunkedResponse::TransactionFailed(chunked_error(&e, Some(&values[..e.offset])))
+        }
+    };
+    // The values may be up to MAX_CHUNKED_LEN bytes, so aren't logged
+    match &rsp {
+        I2cBusChunkedResponse::OK(_) => info!("API {} -> {} bytes", "read_chunked", len),
+        I2cBusChunkedResponse::TransactionFailed(e) => info!(
+            "API {} -> {:?} at offset {:?}",
+            "read_chunked", e.description, e.offset
+        ),
+        rsp => info!("API {} -> {:?}", "read_chunked", rsp),
+    }
+    rsp
+}
+
+// Writes values starting at reg, split into as many transfers as needed
+pub(crate) fn write_chunked(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    values: &models::Values,
+    args: &ext::models::I2cBusChunkedArgs,
+) -> I2cBusChunkedResponse {
+    info!("API {} : {:?} {:?} {:?} {:?}", "write_chunked", bus_id, addr, reg, args);
+    let values = unwrap_or_return_rsp!(write_chunked, check_arg_chunked_values(values));
+    let (bus, addr, reg, chunking) = unwrap_or_return_rsp!(
+        write_chunked,
+        chunked_check_args(identity, Access::Write, bus_id, addr, reg, values.len(), args)
+    );
+    let (mut bus, timer) = lock(&bus, Some(addr), "write_chunked");
+    let rsp = match bus.write_reg_chunked(addr, reg, &values, chunking) {
+        Ok(chunks) => {
+            timer.ok(0, values.len() + chunks * reg.width);
+            I2cBusChunkedResponse::OK(ext::models::I2cBusChunked {
+                chunks: Some(chunks as i32),
+                values: None,
+            })
+        }
+        Err(e) => {
+            timer.failed(errno(&e.error));
+            I2cBusChunkedResponse::TransactionFailed(chunked_error(&e, None))
+        }
+    };
+    info!("API {} -> {:?}", "write_chunked", rsp);
+    rsp
+}
+
+// Reads a register as an integer of the given type, optionally scaled
+pub(crate) fn read_value(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    args: &ext::models::I2cBusValueArgs,
+) -> I2cBusValueResponse {
+    info!("API {} : {:?} {:?} {:?} {:?}", "read_value", bus_id, addr, reg, args);
+    let (bus, addr, reg, value_type) =
+        unwrap_or_return_rsp!(read_value, value_check_args(identity, Access::Read, bus_id, addr, reg, args));
+    let (mut bus, timer) = lock(&bus, Some(addr), "read_value");
+    let mut values: Vec<u8> = vec![0; value_type.width];
+    let result = bus.read_reg(addr, reg, &mut values);
+    record(timer, &result, values.len(), reg.width);
+    let rsp = match result {
+        Ok(_) => {
+            let raw = value_type.decode(&values);
+            I2cBusValueResponse::OK(ext::models::I2cBusValue {
+                raw: Some(raw),
+                value: Some(value_type.scaled(raw)),
+            })
+        }
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "read_value", rsp);
+    rsp
+}
+
+// Writes a register as an integer of the given type, optionally scaled
+pub(crate) fn write_value(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    reg: &models::Reg,
+    write: &ext::models::I2cBusValueWrite,
+    args: &ext::models::I2cBusValueArgs,
+) -> I2cBusValueResponse {
+    info!("API {} : {:?} {:?} {:?} {:?} {:?}", "write_value", bus_id, addr, reg, write, args);
+    let (bus, addr, reg, value_type, raw) =
+        unwrap_or_return_rsp!(write_value, write_value_check_args(identity, bus_id, addr, reg, write, args));
+    let (mut bus, timer) = lock(&bus, Some(addr), "write_value");
+    let result = bus.write_reg_bytes(addr, reg, &value_type.encode(raw));
+    record(timer, &result, 0, reg.width + value_type.width);
+    let rsp = match result {
+        Ok(_) => I2cBusValueResponse::OK(ext::models::I2cBusValue {
+            raw: Some(raw),
+            value: Some(value_type.scaled(raw)),
+        }),
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "write_value", rsp);
+    rsp
+}
+
+pub(crate) fn read_byte(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+) -> I2cBusReadByteResponse {
     info!("API {} : {:?} {:?}", "read_byte", bus_id, addr);
-    let (bus_id, addr) = unwrap_or_return_rsp!(read_byte, read_byte_check_args(&bus_id, &addr));
-    let mut buses = BUSES.lock().unwrap();
+    let (bus, addr) = unwrap_or_return_rsp!(read_byte, read_byte_check_args(identity, &bus_id, &addr));
+    let (mut bus, timer) = lock(&bus, Some(addr), "read_byte");
     let mut values: Vec<u8> = vec![0; 1];
-    let rsp = match buses[bus_id].read_bytes(addr, &mut values) {
+    let result = bus.read_bytes(addr, &mut values);
+    record(timer, &result, values.len(), 0);
+    let rsp = match result {
         Ok(rc) => I2cBusReadByteResponse::OK(models::I2cBusRead {
             ok: Some(rc),
             values: {
@@ -377,16 +2327,19 @@ pub(crate) fn read_byte(bus_id: &models::BusId, addr: &models::Addr) -> I2cBusRe
 
 use std::convert::TryFrom;
 pub(crate) fn read_bytes(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     num_bytes: &models::NumBytes,
 ) -> I2cBusReadBytesResponse {
     info!("API {} : {:?} {:?} {:?}", "read_bytes", bus_id, addr, num_bytes);
-    let (bus_id, addr, num_bytes) =
-        unwrap_or_return_rsp!(read_bytes, read_bytes_check_args(&bus_id, &addr, &num_bytes));
-    let mut buses = BUSES.lock().unwrap();
+    let (bus, addr, num_bytes) =
+        unwrap_or_return_rsp!(read_bytes, read_bytes_check_args(identity, &bus_id, &addr, &num_bytes));
+    let (mut bus, timer) = lock(&bus, Some(addr), "read_bytes");
     let mut values: Vec<u8> = vec![0; num_bytes as usize];
-    let rsp = match buses[bus_id].read_bytes(addr, &mut values) {
+    let result = bus.read_bytes(addr, &mut values);
+    record(timer, &result, values.len(), 0);
+    let rsp = match result {
         Ok(rc) => I2cBusReadBytesResponse::OK(models::I2cBusRead {
             ok: Some(rc),
             values: {
@@ -406,17 +2359,21 @@ pub(crate) fn read_bytes(
 }
 
 pub(crate) fn read_reg(
+    identity: &str,
     bus_id: &models::BusId,
     addr: &models::Addr,
     reg: &models::Reg,
     num_bytes: &models::NumBytes,
+    args: &ext::models::I2cBusRegArgs,
 ) -> I2cBusReadRegResponse {
-    info!("API {} : {:?} {:?} {:?} {:?}", "read_reg", bus_id, addr, reg, num_bytes);
-    let (bus_id, addr, reg, num_bytes) =
-        unwrap_or_return_rsp!(read_reg, read_reg_check_args(&bus_id, &addr, &reg, &num_bytes));
-    let mut buses = BUSES.lock().unwrap();
+    info!("API {} : {:?} {:?} {:?} {:?} {:?}", "read_reg", bus_id, addr, reg, num_bytes, args);
+    let (bus, addr, reg, num_bytes) =
+        unwrap_or_return_rsp!(read_reg, read_reg_check_args(identity, &bus_id, &addr, &reg, &num_bytes, args));
+    let (mut bus, timer) = lock(&bus, Some(addr), "read_reg");
     let mut values: Vec<u8> = vec![0; num_bytes as usize];
-    let rsp = match buses[bus_id].read_reg(addr, reg, &mut values) {
+    let result = bus.read_reg(addr, reg, &mut values);
+    record(timer, &result, values.len(), reg.width);
+    let rsp = match result {
         Ok(rc) => I2cBusReadRegResponse::OK(models::I2cBusRead {
             ok: Some(rc),
             values: {
@@ -435,6 +2392,204 @@ pub(crate) fn read_reg(
     rsp
 }
 
+pub(crate) fn scan(
+    identity: &str,
+    bus_id: &models::BusId,
+    args: &ext::models::I2cBusScanArgs,
+) -> I2cBusScanResponse {
+    info!("API {} : {:?} {:?}", "scan", bus_id, args);
+    let (bus, first, last, mode) =
+        unwrap_or_return_rsp!(scan, scan_check_args(identity, bus_id, args));
+    let mode = |addr| probe_mode(&bus, addr, mode);
+    let (mut bus, timer) = lock(&bus, None, "scan");
+    let (found, claimed) = bus.scan(first, last, mode);
+    timer.ok(0, 0);
+    let rsp = I2cBusScanResponse::OK(ext::models::I2cBusScan {
+        found: Some(found.iter().map(|x| *x as i32).collect()),
+        claimed: Some(claimed.iter().map(|x| *x as i32).collect()),
+    });
+    info!("API {} -> {:?}", "scan", rsp);
+    rsp
+}
+
+pub(crate) fn transaction(
+    identity: &str,
+    bus_id: &models::BusId,
+    txn: &ext::models::I2cBusTransaction,
+) -> I2cBusTransactionResponse {
+    info!("API {} : {:?} {:?}", "transaction", bus_id, txn);
+    let (bus, mut segments) =
+        unwrap_or_return_rsp!(transaction, transaction_check_args(identity, bus_id, txn));
+    let (mut bus, timer) = lock(&bus, segments_addr(&segments), "transaction");
+    let result = bus.transaction(&mut segments);
+    let read = segments.iter().filter(|s| s.read).map(|s| s.data.len()).sum();
+    let written = segments.iter().filter(|s| !s.read).map(|s| s.data.len()).sum();
+    record(timer, &result, read, written);
+    let rsp = match result {
+        Ok(rc) => I2cBusTransactionResponse::OK(ext::models::I2cBusTransactionResult {
+            ok: Some(rc),
+            segments: Some(
+                segments
+                    .iter()
+                    .map(|segment| ext::models::I2cBusSegmentResult {
+                        values: match segment.read {
+                            true => Some(segment.data.iter().map(|x| i32::from(*x)).collect()),
+                            false => None,
+                        },
+                    })
+                    .collect(),
+            ),
+        }),
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "transaction", rsp);
+    rsp
+}
+
+// Waits for a device to ACK its address, as devices don't for a time after
+// an EEPROM write or a reset, probing it on the bus until it does or the
+// timeout expires
+pub(crate) fn wait(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    wait: &ext::models::I2cBusWait,
+) -> I2cBusWaitResponse {
+    info!("API {} : {:?} {:?} {:?}", "wait", bus_id, addr, wait);
+    let (bus, addr, wait) = unwrap_or_return_rsp!(wait, wait_check_args(identity, bus_id, addr, wait));
+    let (mut bus, timer) = lock(&bus, Some(addr), "wait");
+    let result = bus.poll_ack(addr, wait.mode, wait.timeout, wait.interval);
+    record(timer, &result, 0, 0);
+    let rsp = match result {
+        Ok(waited) => I2cBusWaitResponse::OK(ext::models::I2cBusWaitResult {
+            waited: Some(waited.as_secs_f64() * 1000.0),
+        }),
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "wait", rsp);
+    rsp
+}
+
+pub(crate) fn smbus(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    op: &ext::models::I2cBusSmbus,
+) -> I2cBusSmbusResponse {
+    info!("API {} : {:?} {:?} {:?}", "smbus", bus_id, addr, op);
+    let (bus, addr, op) = unwrap_or_return_rsp!(smbus, smbus_check_args(identity, bus_id, addr, op));
+    let (mut bus, timer) = lock(&bus, Some(addr), "smbus");
+    let result = bus.smbus(addr, &op);
+    let (read, written) = smbus_bytes(&op, result.as_ref().ok());
+    record(timer, &result, read, written);
+    let rsp = match result {
+        Ok(data) => I2cBusSmbusResponse::OK(match data {
+            SmbusData::None => ext::models::I2cBusSmbusResult {
+                value: None,
+                values: None,
+            },
+            SmbusData::Byte(x) => ext::models::I2cBusSmbusResult {
+                value: Some(i32::from(x)),
+                values: None,
+            },
+            SmbusData::Word(x) => ext::models::I2cBusSmbusResult {
+                value: Some(i32::from(x)),
+                values: None,
+            },
+            SmbusData::Block(x) => ext::models::I2cBusSmbusResult {
+                value: None,
+                values: Some(x.iter().map(|x| i32::from(*x)).collect()),
+            },
+        }),
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "smbus", rsp);
+    rsp
+}
+
+// Number of registers in each row of a dump, as per i2cdump
+const DUMP_ROW_LEN: usize = 16;
+
+// Splits dumped values, starting at register first, into rows aligned to
+// DUMP_ROW_LEN registers
+fn dump_rows(first: u8, values: &[Option<u8>]) -> Vec<ext::models::I2cBusDumpRow> {
+    let mut rows: Vec<ext::models::I2cBusDumpRow> = Vec::new();
+    let mut reg = first as usize;
+    let end = reg + values.len();
+    let mut values = values.iter();
+    while reg < end {
+        let len = std::cmp::min(DUMP_ROW_LEN - reg % DUMP_ROW_LEN, end - reg);
+        rows.push(ext::models::I2cBusDumpRow {
+            reg: Some(reg as i32),
+            values: Some(values.by_ref().take(len).map(|x| x.map(i32::from)).collect()),
+        });
+        reg += len;
+    }
+    rows
+}
+
+// Renders dump rows in i2cdump's hex and ASCII layout
+fn dump_text(rows: &[ext::models::I2cBusDumpRow]) -> String {
+    let mut text =
+        String::from("     0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f    0123456789abcdef\n");
+    for row in rows {
+        let reg = row.reg.unwrap_or(0) as usize;
+        let values = row.values.clone().unwrap_or_default();
+        let pad = reg % DUMP_ROW_LEN;
+        let mut hex = "   ".repeat(pad);
+        let mut ascii = " ".repeat(pad);
+        for value in &values {
+            match value {
+                Some(x) => {
+                    hex += &format!("{:02x} ", x);
+                    ascii.push(match *x {
+                        0x00 | 0xff => '.',
+                        0x20..=0x7e => *x as u8 as char,
+                        _ => '?',
+                    });
+                }
+                None => {
+                    hex += "XX ";
+                    ascii.push('X');
+                }
+            }
+        }
+        let tail = DUMP_ROW_LEN - pad - values.len();
+        hex += &"   ".repeat(tail);
+        ascii += &" ".repeat(tail);
+        text += &format!("{:02x}: {}   {}\n", reg - pad, hex, ascii);
+    }
+    text
+}
+
+pub(crate) fn dump(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    args: &ext::models::I2cBusDumpArgs,
+) -> I2cBusDumpResponse {
+    info!("API {} : {:?} {:?} {:?}", "dump", bus_id, addr, args);
+    let (bus, addr, first, last, mode, text) =
+        unwrap_or_return_rsp!(dump, dump_check_args(identity, bus_id, addr, args));
+    let (mut bus, timer) = lock(&bus, Some(addr), "dump");
+    let result = bus.dump(addr, first, last, mode);
+    let read = result.as_ref().map_or(0, |values| values.iter().flatten().count());
+    record(timer, &result, read, 0);
+    let rsp = match result {
+        Ok(values) => {
+            let rows = dump_rows(first, &values);
+            if text {
+                I2cBusDumpResponse::OKText(dump_text(&rows))
+            } else {
+                I2cBusDumpResponse::OK(ext::models::I2cBusDump { rows: Some(rows) })
+            }
+        }
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "dump", rsp);
+    rsp
+}
+
 pub(crate) fn get_api() -> I2cBusApiResponse {
     // Read in the file
     info!("API {}", "get_api");
@@ -453,16 +2608,444 @@ pub(crate) fn get_api() -> I2cBusApiResponse {
     rsp
 }
 
-pub(crate) fn get_buses() -> I2cBusListResponse {
-    let buses = BUSES.lock().unwrap();
+pub(crate) fn get_buses() -> ext::I2cBusListResponse {
     info!("API {}", "get_buses");
-    let rsp = I2cBusListResponse::OK(
-        buses
+    let rsp = ext::I2cBusListResponse::OK(
+        buses()
             .iter()
-            .map(<models::I2cBusList>::from)
-            .collect::<Vec<models::I2cBusList>>(),
+            .map(|bus| <ext::models::I2cBusList>::from(&**bus))
+            .collect::<Vec<ext::models::I2cBusList>>(),
     );
     info!("API {} -> {:?}", "get_buses", rsp);
     rsp
 }
 
+pub(crate) fn get_profiles() -> ext::I2cBusProfilesResponse {
+    let mut profiles: Vec<Profile> = PROFILES.values().cloned().collect();
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    ext::I2cBusProfilesResponse::OK(profiles)
+}
+
+fn device(bus: &i2c::BusInfo, addr: u16, profile: Option<String>) -> I2cBusDeviceResponse {
+    I2cBusDeviceResponse::OK(ext::models::I2cBusDevice {
+        bus: Some(bus.id as i32),
+        addr: Some(i32::from(addr)),
+        profile,
+    })
+}
+
+pub(crate) fn get_device(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+) -> I2cBusDeviceResponse {
+    info!("API {} : {:?} {:?}", "get_device", bus_id, addr);
+    let (bus, addr) = unwrap_or_return_rsp!(get_device, device_check_args(identity, Access::Read, bus_id, addr));
+    let profile = DEVICES.lock().unwrap().get(&(bus.id, addr)).cloned();
+    let rsp = device(&bus, addr, profile);
+    info!("API {} -> {:?}", "get_device", rsp);
+    rsp
+}
+
+pub(crate) fn bind_device(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    body: &ext::models::I2cBusDevice,
+) -> I2cBusDeviceResponse {
+    info!("API {} : {:?} {:?} {:?}", "bind_device", bus_id, addr, body);
+    let (bus, addr, profile) =
+        unwrap_or_return_rsp!(bind_device, bind_device_check_args(identity, bus_id, addr, body));
+    DEVICES.lock().unwrap().insert((bus.id, addr), profile.clone());
+    let rsp = device(&bus, addr, Some(profile));
+    info!("API {} -> {:?}", "bind_device", rsp);
+    rsp
+}
+
+pub(crate) fn unbind_device(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+) -> I2cBusDeviceResponse {
+    info!("API {} : {:?} {:?}", "unbind_device", bus_id, addr);
+    let (bus, addr) = unwrap_or_return_rsp!(unbind_device, device_check_args(identity, Access::Write, bus_id, addr));
+    DEVICES.lock().unwrap().remove(&(bus.id, addr));
+    let rsp = device(&bus, addr, None);
+    info!("API {} -> {:?}", "unbind_device", rsp);
+    rsp
+}
+
+fn named_reg(register: &Register, value: u32) -> I2cBusNamedRegResponse {
+    I2cBusNamedRegResponse::OK(ext::models::I2cBusNamedReg {
+        name: Some(register.name.clone()),
+        reg: Some(i64::from(register.reg)),
+        value: Some(i64::from(value)),
+        fields: match register.fields.is_empty() {
+            true => None,
+            false => Some(
+                register
+                    .fields
+                    .iter()
+                    .map(|field| (field.name.clone(), i64::from(field.get(value))))
+                    .collect(),
+            ),
+        },
+    })
+}
+
+pub(crate) fn read_named_reg(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    name: &str,
+) -> I2cBusNamedRegResponse {
+    info!("API {} : {:?} {:?} {:?}", "read_named_reg", bus_id, addr, name);
+    let (bus, addr, register, pointer) = unwrap_or_return_rsp!(
+        read_named_reg,
+        named_reg_check_args(identity, Access::Read, bus_id, addr, name)
+    );
+    let (mut bus, timer) = lock(&bus, Some(addr), "read_named_reg");
+    let mut values: Vec<u8> = vec![0; register.width as usize];
+    let result = bus.read_reg(addr, pointer, &mut values);
+    record(timer, &result, values.len(), pointer.width);
+    let rsp = match result {
+        Ok(_) => named_reg(&register, register.decode(&values)),
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "read_named_reg", rsp);
+    rsp
+}
+
+// Writes a named register.  If only some fields are given, the register is
+// read and written while holding the bus lock, so the other fields can't be
+// changed in between.
+pub(crate) fn write_named_reg(
+    identity: &str,
+    bus_id: &models::BusId,
+    addr: &models::Addr,
+    name: &str,
+    write: &ext::models::I2cBusNamedRegWrite,
+) -> I2cBusNamedRegResponse {
+    info!("API {} : {:?} {:?} {:?} {:?}", "write_named_reg", bus_id, addr, name, write);
+    let (bus, addr, register, pointer, write) = unwrap_or_return_rsp!(
+        write_named_reg,
+        write_named_reg_check_args(identity, bus_id, addr, name, write)
+    );
+    let (mut bus, timer) = lock(&bus, Some(addr), "write_named_reg");
+    let mut values: Vec<u8> = vec![0; register.width as usize];
+    let current = match write.value {
+        Some(_) => Ok(0),
+        None => bus.read_reg(addr, pointer, &mut values).map(|_| register.decode(&values)),
+    };
+    let value = current.map(|current| write.apply(current));
+    let result = value.and_then(|value| {
+        bus.write_reg_bytes(addr, pointer, &register.encode(value)).map(|_| value)
+    });
+    let (read, written) = match write.value {
+        Some(_) => (0, pointer.width + values.len()),
+        None => (values.len(), 2 * pointer.width + values.len()),
+    };
+    record(timer, &result, read, written);
+    let rsp = match result {
+        Ok(value) => named_reg(&register, value),
+        Err(e) => e.into(),
+    };
+    info!("API {} -> {:?}", "write_named_reg", rsp);
+    rsp
+}
+
+pub(crate) fn health() -> ext::HealthResponse {
+    ext::HealthResponse::OK(ext::models::I2cBusHealth {
+        status: Some("ok".to_string()),
+    })
+}
+
+// Reports whether the server is ready: buses were found, the pool isn't
+// saturated, and every required bus is present and required device responds.
+// As probing a device needs a pool thread, devices aren't probed (and so are
+// reported as unknown) if the pool was saturated when the request arrived.
+// Nor are they if their bus is held by another request for half the request
+// timeout, so a stuck bus fails readiness rather than timing it out and
+// leaving this thread waiting for the bus.
+pub(crate) fn ready(pool: PoolStatus) -> ext::ReadyResponse {
+    let start = Instant::now();
+    let buses = buses();
+    let enumerated = ENUMERATED.load(Ordering::SeqCst);
+    let required: Vec<ext::models::I2cBusRequired> = REQUIRED
+        .iter()
+        .map(|required| {
+            let bus = buses.iter().find(|bus| bus.id == required.bus);
+            let present = match (bus, required.addr) {
+                (None, _) => Some(false),
+                (Some(_), None) => Some(true),
+                (Some(_), Some(_)) if pool.saturated() => None,
+                (Some(bus), Some(addr)) => {
+                    let wait = (*TIMEOUT / 2).checked_sub(start.elapsed()).unwrap_or_default();
+                    let mode = probe_mode(bus, addr, i2c::ProbeMode::Auto);
+                    bus.try_lock_for(wait).map(|mut bus| bus.probe(addr, mode))
+                }
+            };
+            ext::models::I2cBusRequired {
+                bus: Some(required.bus as i32),
+                addr: required.addr.map(i32::from),
+                present,
+            }
+        })
+        .collect();
+    let ready = enumerated
+        && !pool.saturated()
+        && required.iter().all(|required| required.present == Some(true));
+    let status = ext::models::I2cBusReady {
+        ready: Some(ready),
+        enumerated: Some(enumerated),
+        buses: Some(buses.len() as i32),
+        workers: Some(pool.workers as i32),
+        busy: Some(pool.busy as i32),
+        saturated: Some(pool.saturated()),
+        required: Some(required),
+    };
+    let rsp = match ready {
+        true => ext::ReadyResponse::OK(status),
+        false => ext::ReadyResponse::ServiceUnavailable(status),
+    };
+    if !ready {
+        warn!("API {} -> {:?}", "ready", rsp);
+    }
+    rsp
+}
+
+pub(crate) fn metrics() -> ext::MetricsResponse {
+    ext::MetricsResponse::OK(metrics::render())
+}
+
+pub(crate) fn rescan(identity: &str) -> ext::I2cBusRescanResponse {
+    info!("API {}", "rescan");
+    unwrap_or_return_rsp!(rescan, rescan_check_args(identity));
+    rescan_buses();
+    let rsp = match get_buses() {
+        ext::I2cBusListResponse::OK(buses) => ext::I2cBusRescanResponse::OK(buses),
+    };
+    info!("API {} -> {:?}", "rescan", rsp);
+    rsp
+}
+
+pub(crate) fn get_bus_info(bus_id: &models::BusId) -> I2cBusInfoResponse {
+    info!("API {} : {:?}", "get_bus_info", bus_id);
+    let bus = unwrap_or_return_rsp!(get_bus_info, check_arg_bus_id(bus_id));
+    let rsp = I2cBusInfoResponse::OK(<ext::models::I2cBusList>::from(&*bus));
+    info!("API {} -> {:?}", "get_bus_info", rsp);
+    rsp
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::errno::Errno;
+    use std::sync::Once;
+
+    // Identities the test policy lets read, and write, anything
+    const READER: &str = "reader";
+    const WRITER: &str = "writer";
+
+    // The configuration is loaded into statics on first use, so every test
+    // shares it: simulated buses 0 and 1 with devices at 0x20 and 0x50, the
+    // test policy, and 0x20 on bus 1 write-protected
+    fn setup() {
+        static SETUP: Once = Once::new();
+        SETUP.call_once(|| {
+            let path = env::temp_dir().join(format!("i2cbus-test-policy-{}", std::process::id()));
+            fs::write(&path, "reader read * * *\nwriter write * * *\n").unwrap();
+            env::set_var(policy::I2C_POLICY_VAR, &path);
+            env::set_var(I2C_SIM_VAR, "0x20,0x50:512;0x20,0x50:512");
+            env::set_var(policy::I2C_PROTECT_VAR, "1:0x20");
+        });
+    }
+
+    macro_rules! assert_forbidden {
+        ($rsp:expr, $type:ident, $errno:expr) => {
+            match $rsp {
+                $type::Forbidden(forbidden) => assert_eq!(forbidden.error, Some($errno as i32)),
+                rsp => panic!("expected Forbidden, got {:?}", rsp),
+            }
+        };
+    }
+
+    fn unwrap_args<T>(rc: Result<T, ArgError>) -> T {
+        match rc {
+            Ok(rc) => rc,
+            Err(_) => panic!("invalid arguments"),
+        }
+    }
+
+    fn scan_args(mode: &str) -> ext::models::I2cBusScanArgs {
+        ext::models::I2cBusScanArgs {
+            first: None,
+            last: None,
+            mode: Some(mode.to_string()),
+        }
+    }
+
+    fn wait_args(mode: &str) -> ext::models::I2cBusWait {
+        ext::models::I2cBusWait {
+            mode: Some(mode.to_string()),
+            timeout: Some(5),
+            interval: None,
+        }
+    }
+
+    #[test]
+    fn writing_probes_need_write_access() {
+        setup();
+        for mode in &["quick", "auto"] {
+            assert_forbidden!(scan(READER, &0.into(), &scan_args(mode)), I2cBusScanResponse, Errno::EACCES);
+            assert_forbidden!(wait(READER, &0.into(), &0x20.into(), &wait_args(mode)), I2cBusWaitResponse, Errno::EACCES);
+            assert!(matches!(scan(WRITER, &0.into(), &scan_args(mode)), I2cBusScanResponse::OK(_)));
+            assert!(matches!(wait(WRITER, &0.into(), &0x20.into(), &wait_args(mode)), I2cBusWaitResponse::OK(_)));
+        }
+    }
+
+    #[test]
+    fn read_probes_need_read_access() {
+        setup();
+        match scan(READER, &0.into(), &scan_args("read")) {
+            I2cBusScanResponse::OK(scan) => assert_eq!(scan.found, Some(vec![0x20, 0x50])),
+            rsp => panic!("{:?}", rsp),
+        }
+        assert!(matches!(wait(READER, &0.into(), &0x20.into(), &wait_args("read")), I2cBusWaitResponse::OK(_)));
+    }
+
+    #[test]
+    fn transaction_wait_segments_need_access_for_mode() {
+        setup();
+        let txn = |mode: &str| ext::models::I2cBusTransaction {
+            segments: Some(vec![ext::models::I2cBusSegment {
+                addr: Some(0x20),
+                write: None,
+                read: None,
+                wait: Some(wait_args(mode)),
+            }]),
+        };
+        let rsp = transaction(READER, &0.into(), &txn("quick"));
+        assert_forbidden!(rsp, I2cBusTransactionResponse, Errno::EACCES);
+        assert!(matches!(transaction(READER, &0.into(), &txn("read")), I2cBusTransactionResponse::OK(_)));
+        assert!(matches!(transaction(WRITER, &0.into(), &txn("quick")), I2cBusTransactionResponse::OK(_)));
+    }
+
+    #[test]
+    fn auto_probes_read_protected_addresses() {
+        setup();
+        let bus = unwrap_args(check_arg_bus_id(&1.into()));
+        assert_eq!(probe_mode(&bus, 0x20, i2c::ProbeMode::Auto), i2c::ProbeMode::Read);
+        assert_eq!(probe_mode(&bus, 0x21, i2c::ProbeMode::Auto), i2c::ProbeMode::Auto);
+        assert_eq!(probe_mode(&bus, 0x20, i2c::ProbeMode::Quick), i2c::ProbeMode::Quick);
+        let bus = unwrap_args(check_arg_bus_id(&0.into()));
+        assert_eq!(probe_mode(&bus, 0x20, i2c::ProbeMode::Auto), i2c::ProbeMode::Auto);
+        match scan(WRITER, &1.into(), &scan_args("auto")) {
+            I2cBusScanResponse::OK(scan) => assert_eq!(scan.found, Some(vec![0x20, 0x50])),
+            rsp => panic!("{:?}", rsp),
+        }
+        let (_, _, wait) = unwrap_args(wait_check_args(WRITER, &1.into(), &0x20.into(), &wait_args("auto")));
+        assert_eq!(wait.mode, i2c::ProbeMode::Read);
+    }
+
+    #[test]
+    fn quick_probes_refuse_protected_addresses() {
+        setup();
+        assert_forbidden!(scan(WRITER, &1.into(), &scan_args("quick")), I2cBusScanResponse, Errno::EROFS);
+        let args = ext::models::I2cBusScanArgs {
+            first: Some(0x21),
+            ..scan_args("quick")
+        };
+        assert!(matches!(scan(WRITER, &1.into(), &args), I2cBusScanResponse::OK(_)));
+        assert_forbidden!(wait(WRITER, &1.into(), &0x20.into(), &wait_args("quick")), I2cBusWaitResponse, Errno::EROFS);
+    }
+
+    fn values(values: &[i32]) -> models::Values {
+        models::Values {
+            values: Some(values.iter().map(|x| models::I2cByte::from(*x)).collect()),
+        }
+    }
+
+    // The argument rejected by a check_args function
+    fn bad_arg<T>(rc: Result<T, ArgError>) -> String {
+        match rc {
+            Err(ArgError::Error(e)) => e.arg.unwrap(),
+            Err(_) => panic!("expected an argument error, got a policy violation"),
+            Ok(_) => panic!("expected an argument error"),
+        }
+    }
+
+    #[test]
+    fn writes_read_back_from_sim() {
+        setup();
+        let rsp = write_bytes(WRITER, &0.into(), &0x20.into(), &values(&[0x10, 0xab, 0xcd]));
+        assert!(matches!(rsp, I2cBusWriteBytesResponse::OK(_)));
+        // Other tests probe the device, moving its register pointer, so set
+        // it and read back in one transaction
+        let segment = |write: Option<Vec<i32>>, read: Option<i32>| ext::models::I2cBusSegment {
+            addr: Some(0x20),
+            write,
+            read,
+            wait: None,
+        };
+        let txn = ext::models::I2cBusTransaction {
+            segments: Some(vec![segment(Some(vec![0x10]), None), segment(None, Some(2))]),
+        };
+        match transaction(WRITER, &0.into(), &txn) {
+            I2cBusTransactionResponse::OK(result) => {
+                let segments = result.segments.unwrap();
+                assert_eq!(segments[1].values, Some(vec![0xab, 0xcd]));
+            }
+            rsp => panic!("{:?}", rsp),
+        }
+        assert!(matches!(read_bytes(READER, &0.into(), &0x20.into(), &2.into()), I2cBusReadBytesResponse::OK(_)));
+    }
+
+    #[test]
+    fn invalid_args_are_rejected() {
+        setup();
+        assert_eq!(bad_arg(read_bytes_check_args(READER, &2.into(), &0x20.into(), &1.into())), "busId");
+        assert_eq!(bad_arg(read_bytes_check_args(READER, &0.into(), &0x80.into(), &1.into())), "addr");
+        assert_eq!(bad_arg(read_bytes_check_args(READER, &0.into(), &0x20.into(), &256.into())), "numBytes");
+        assert_eq!(bad_arg(write_bytes_check_args(WRITER, &0.into(), &0x20.into(), &values(&[0x100]))), "values");
+        let args = ext::models::I2cBusScanArgs {
+            first: Some(0x50),
+            last: Some(0x20),
+            mode: None,
+        };
+        assert_eq!(bad_arg(scan_check_args(WRITER, &0.into(), &args)), "last");
+        assert_eq!(bad_arg(scan_check_args(WRITER, &0.into(), &scan_args("slow"))), "mode");
+        let wait = ext::models::I2cBusWait {
+            interval: Some(0),
+            ..wait_args("read")
+        };
+        assert_eq!(bad_arg(wait_check_args(WRITER, &0.into(), &0x20.into(), &wait)), "interval");
+        match read_bytes(READER, &2.into(), &0x20.into(), &1.into()) {
+            I2cBusReadBytesResponse::BadRequest(e) => assert_eq!(e.arg, Some("busId".to_string())),
+            rsp => panic!("{:?}", rsp),
+        }
+    }
+
+    #[test]
+    fn policy_forbids_before_bus_access() {
+        setup();
+        let rsp = write_bytes(READER, &0.into(), &0x20.into(), &values(&[0x10, 0x01]));
+        assert_forbidden!(rsp, I2cBusWriteBytesResponse, Errno::EACCES);
+        let rsp = read_bytes("stranger", &0.into(), &0x20.into(), &1.into());
+        assert_forbidden!(rsp, I2cBusReadBytesResponse, Errno::EACCES);
+        let rsp = write_byte(WRITER, &1.into(), &0x20.into(), &0x01.into());
+        assert_forbidden!(rsp, I2cBusWriteByteResponse, Errno::EROFS);
+    }
+
+    #[test]
+    fn rescan_needs_write_access_to_every_bus() {
+        use ext::I2cBusRescanResponse;
+        setup();
+        assert_forbidden!(rescan(READER), I2cBusRescanResponse, Errno::EACCES);
+        match rescan(WRITER) {
+            I2cBusRescanResponse::OK(buses) => assert_eq!(buses.len(), 2),
+            rsp => panic!("{:?}", rsp),
+        }
+    }
+}
@@ -1,39 +1,422 @@
+#[path = "funcs.rs"] pub(crate) mod funcs;
+#[path = "sim.rs"] pub(crate) mod sim;
+#[path = "smbus.rs"] pub(crate) mod smbus;
 use i2cdev2::core::I2CBus;
+use i2cdev2::core::I2CMsgFlags;
 use i2cdev2::linux::{I2CMsg, LinuxI2CBus, LinuxI2CError};
 use std::error::Error;
 use std::fmt;
+use smbus::{SmbusData, SmbusOp};
+use std::fs;
+use std::os::unix::io::AsRawFd;
 use std::fs::read_dir;
+use std::path::Path;
 use std::result::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{debug, warn};
+
+// Offset added to a 10-bit address to distinguish it from a 7-bit one, as
+// the kernel does for sysfs device names and new_device.  So 0x50 is a 7-bit
+// address, and 0xa050 is 10-bit address 0x050.
+pub(crate) const ADDR_TEN_BIT: u16 = 0xa000;
+
+// Returns whether addr is a 10-bit address, offset by ADDR_TEN_BIT
+pub(crate) fn is_ten_bit(addr: u16) -> bool {
+    addr & ADDR_TEN_BIT == ADDR_TEN_BIT
+}
+
+// A single I2C message, as passed to a Backend.  Mirrors i2cdev2's I2CMsg,
+// but with public fields so that backends other than Linux can fill in read
+// data.
+pub(crate) struct Msg<'a> {
+    // Slave address, as sent on the wire - 0x00-0x7f, or 0x000-0x3ff if
+    // I2C_M_TEN is set
+    pub addr: u16,
+
+    // I2CMsgFlags bits, e.g. I2C_M_RD
+    pub flags: u16,
+
+    // Data to be written, or buffer to be read into
+    pub data: &'a mut Vec<u8>,
+}
+
+impl<'a> Msg<'a> {
+    // Creates a write message.  A 10-bit addr (offset by ADDR_TEN_BIT) has
+    // the offset removed and I2C_M_TEN set.
+    pub(crate) fn new(addr: u16, data: &'a mut Vec<u8>) -> Self {
+        if is_ten_bit(addr) {
+            Msg {
+                addr: addr & !ADDR_TEN_BIT,
+                flags: I2CMsgFlags::I2C_M_TEN.bits(),
+                data,
+            }
+        } else {
+            Msg { addr, flags: 0, data }
+        }
+    }
+
+    pub(crate) fn set_read(&mut self) {
+        self.flags |= I2CMsgFlags::I2C_M_RD.bits();
+    }
+
+    pub(crate) fn is_read(&self) -> bool {
+        self.flags & I2CMsgFlags::I2C_M_RD.bits() != 0
+    }
+
+    // Returns the slave address in the form passed to new, i.e. offset by
+    // ADDR_TEN_BIT if I2C_M_TEN is set
+    pub(crate) fn full_addr(&self) -> u16 {
+        if self.flags & I2CMsgFlags::I2C_M_TEN.bits() != 0 {
+            self.addr | ADDR_TEN_BIT
+        } else {
+            self.addr
+        }
+    }
+}
+
+// A segment of a combined transaction, which owns its data
+pub(crate) struct Segment {
+    // Slave address, offset by ADDR_TEN_BIT if 10-bit
+    pub addr: u16,
+
+    // Whether this segment is a read, rather than a write
+    pub read: bool,
+
+    // Data to be written, or the data read
+    pub data: Vec<u8>,
+
+    // If set, rather than a read or write this segment waits for the device
+    // to ACK its address
+    pub wait: Option<AckWait>,
+}
+
+// Waiting for a device to ACK its address - how to probe it, for how long,
+// and how often
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct AckWait {
+    pub mode: ProbeMode,
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+// Maximum number of messages the kernel accepts in a single I2C_RDWR ioctl
+pub(crate) const MAX_RDWR_MSGS: usize = 42;
+
+// Maximum number of bytes the kernel accepts in a single message
+pub(crate) const MAX_MSG_LEN: usize = 8192;
+
+// How often try_lock_for retries a bus which is locked
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// Implemented by anything which can carry out I2C transactions on behalf of
+// a BusInfo - the real Linux I2C bus, or a simulated one
+pub(crate) trait Backend: Send {
+    // Issues the provided messages as a single combined transaction
+    fn rdwr(&mut self, msgs: &mut Vec<Msg>) -> Result<i32, BusError>;
+
+    // Carries out an SMBus operation on the device at addr, offset by
+    // ADDR_TEN_BIT if 10-bit
+    fn smbus(&mut self, addr: u16, op: &SmbusOp) -> Result<SmbusData, BusError>;
+
+    // Returns the adapter's functionality, as I2C_FUNC_* flags
+    fn funcs(&mut self) -> Result<u64, BusError>;
+}
+
+impl Backend for LinuxI2CBus {
+    fn rdwr(&mut self, msgs: &mut Vec<Msg>) -> Result<i32, BusError> {
+        let mut i2c_msgs = msgs
+            .iter_mut()
+            .map(|msg| {
+                let mut i2c_msg = I2CMsg::new(msg.addr, msg.data);
+                i2c_msg.set_flags(msg.flags);
+                i2c_msg
+            })
+            .collect::<Vec<I2CMsg>>();
+        I2CBus::rdwr(self, &mut i2c_msgs).map_err(From::from)
+    }
+
+    fn smbus(&mut self, addr: u16, op: &SmbusOp) -> Result<SmbusData, BusError> {
+        smbus::smbus(self.as_raw_fd(), addr, op)
+    }
+
+    fn funcs(&mut self) -> Result<u64, BusError> {
+        funcs::funcs(self.as_raw_fd())
+    }
+}
+
+// How to probe for a device when scanning, as per i2cdetect's -q and -r
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProbeMode {
+    // Read byte for 0x30-0x37 and 0x50-0x5f, where a quick write can corrupt
+    // some EEPROMs, and quick write everywhere else, where a read can lock up
+    // some write-only devices
+    Auto,
+
+    // Quick (zero length) write
+    Quick,
+
+    // Single byte read
+    Read,
+}
+
+// How to access registers when dumping them, as per i2cdump's b, w and i
+// modes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DumpMode {
+    // One register per transaction
+    Byte,
+
+    // Two registers per transaction
+    Word,
+
+    // Up to 32 registers per transaction
+    Block,
+}
+
+// A register, and how its address is sent to the device - as a 1, 2 or 4
+// byte register pointer, most significant byte first unless lsb_first.
+// Devices with more than 256 registers, such as 24Cxx EEPROMs larger than
+// 2KiB, typically take 2 byte, most significant first, pointers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RegPointer {
+    pub reg: u32,
+    pub width: usize,
+    pub lsb_first: bool,
+}
+
+impl RegPointer {
+    // A single byte pointer, as most devices take
+    pub(crate) fn byte(reg: u8) -> RegPointer {
+        RegPointer {
+            reg: u32::from(reg),
+            width: 1,
+            lsb_first: false,
+        }
+    }
+
+    // The highest register a pointer of this width can address
+    pub(crate) fn max(width: usize) -> u32 {
+        match width {
+            4 => u32::MAX,
+            width => (1 << (8 * width)) - 1,
+        }
+    }
+
+    // The bytes to send to set the device's register pointer
+    pub(crate) fn bytes(&self) -> Vec<u8> {
+        let bytes = (0..self.width).map(|i| (self.reg >> (8 * i)) as u8);
+        match self.lsb_first {
+            true => bytes.collect(),
+            false => bytes.rev().collect(),
+        }
+    }
+}
+
+// How a read-modify-write changes a register's value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ModifyOp {
+    // Replaces the bits in mask with those of value
+    Update { mask: u8, value: u8 },
+
+    // Sets, clears or toggles the bits in the mask
+    Set(u8),
+    Clear(u8),
+    Toggle(u8),
+}
+
+impl ModifyOp {
+    pub(crate) fn apply(&self, value: u8) -> u8 {
+        match *self {
+            ModifyOp::Update { mask, value: new } => (value & !mask) | (new & mask),
+            ModifyOp::Set(mask) => value | mask,
+            ModifyOp::Clear(mask) => value & !mask,
+            ModifyOp::Toggle(mask) => value ^ mask,
+        }
+    }
+}
+
+// How a large transfer to or from registers is split into chunks, each a
+// separate transfer: at most len bytes per message, including the register
+// pointer for writes, with write chunks not crossing a multiple of page
+// registers (as for EEPROM page writes).  The register pointer is advanced
+// for each chunk, unless fixed, for devices where the register is a control
+// byte or FIFO sent before every chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Chunking {
+    pub len: usize,
+    pub page: Option<u32>,
+    pub fixed: bool,
+}
+
+impl Chunking {
+    // The register pointer for the chunk offset bytes into the transfer
+    fn pointer(&self, reg: RegPointer, offset: usize) -> RegPointer {
+        match self.fixed {
+            true => reg,
+            false => RegPointer {
+                reg: reg.reg + offset as u32,
+                ..reg
+            },
+        }
+    }
+}
+
+// A chunked transfer which failed part way through - offset is how many bytes
+// were transferred before the chunk which failed
+#[derive(Debug)]
+pub(crate) struct ChunkError {
+    pub offset: usize,
+    pub error: BusError,
+}
 
 pub(crate) struct BusInfo {
-    // ID for this bus, starting at 0
+    // ID for this bus - the kernel's adapter number, so /dev/i2c-<id>
     pub id: usize,
 
     // Local filesystem path for this bus, likely to be /dev/i2c-<id>
     pub path: String,
 
-    // LinuxI2CBus instance for this bus
-    pub bus: LinuxI2CBus,
+    // Adapter name, as reported by sysfs, if available
+    pub name: Option<String>,
+
+    // Adapter functionality, as I2C_FUNC_* flags, if it could be queried
+    pub funcs: Option<u64>,
+
+    // Backend instance for this bus - a LinuxI2CBus unless simulated.  Each
+    // bus has its own lock, so a slow transfer on one bus doesn't hold up
+    // requests to the others.
+    bus: Mutex<Box<dyn Backend>>,
+
+    // Set once the bus has gone from the system, after which all operations
+    // fail with ENODEV
+    removed: AtomicBool,
 }
 
 impl BusInfo {
-    fn new(id: usize, path: String) -> Result<BusInfo, BusError> {
+    fn new(id: usize, path: String, name: Option<String>) -> Result<BusInfo, BusError> {
         let bus = LinuxI2CBus::new(path.clone())?;
-        Ok(BusInfo { id, path, bus })
+        Ok(BusInfo::with_backend(id, path, name, Box::new(bus)))
     }
 
-    fn rdwr(&mut self, msgs: &mut Vec<I2CMsg>) -> Result<i32, BusError> {
-        self.bus.rdwr(msgs).map_err(From::from)
+    pub(crate) fn with_backend(
+        id: usize,
+        path: String,
+        name: Option<String>,
+        mut bus: Box<dyn Backend>,
+    ) -> BusInfo {
+        let funcs = match bus.funcs() {
+            Ok(funcs) => Some(funcs),
+            Err(e) => {
+                warn!("Failed to query functionality of I2C bus {} {}", path, e);
+                None
+            }
+        };
+        BusInfo {
+            id,
+            path,
+            name,
+            funcs,
+            bus: Mutex::new(bus),
+            removed: AtomicBool::new(false),
+        }
+    }
+
+    // Returns whether the adapter supports all of the given I2C_FUNC_*
+    // flags.  If the functionality couldn't be queried, assumes it does, and
+    // leaves it to the adapter to reject the operation.
+    pub(crate) fn supports(&self, funcs: u64) -> bool {
+        match self.funcs {
+            Some(supported) => supported & funcs == funcs,
+            None => true,
+        }
+    }
+
+    // Locks the bus for exclusive use.  The lock is held until the returned
+    // Bus is dropped, so a sequence of operations on it (e.g. a scan) isn't
+    // interleaved with other requests to this bus.  A request which panics
+    // while holding the lock poisons it, but leaves the backend usable, so
+    // the bus is recovered rather than failing every later request.
+    pub(crate) fn lock(&self) -> Bus<'_> {
+        Bus {
+            info: self,
+            backend: self.bus.lock().unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+
+    // Locks the bus as lock does, unless it's still held by another request
+    // after timeout, as it may be if a transfer is stuck
+    pub(crate) fn try_lock_for(&self, timeout: Duration) -> Option<Bus<'_>> {
+        let start = Instant::now();
+        loop {
+            match self.bus.try_lock() {
+                Ok(backend) => return Some(Bus { info: self, backend }),
+                Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                    thread::sleep(LOCK_POLL_INTERVAL)
+                }
+                Err(TryLockError::WouldBlock) => return None,
+                Err(TryLockError::Poisoned(e)) => {
+                    return Some(Bus {
+                        info: self,
+                        backend: e.into_inner(),
+                    })
+                }
+            }
+        }
+    }
+
+    // Marks the bus as removed from the system
+    pub(crate) fn remove(&self) {
+        self.removed.store(true, Ordering::SeqCst);
+    }
+}
+
+// A locked BusInfo, through which transfers are made
+pub(crate) struct Bus<'a> {
+    // The bus this was locked from
+    info: &'a BusInfo,
+
+    // Backend instance for this bus, locked
+    backend: MutexGuard<'a, Box<dyn Backend>>,
+}
+
+impl<'a> Bus<'a> {
+    // Fails if the bus has been removed, rather than using a stale backend
+    fn check_removed(&self) -> Result<(), BusError> {
+        if self.info.removed.load(Ordering::SeqCst) {
+            Err(BusError::from(LinuxI2CError::from(nix::Error::Sys(
+                nix::errno::Errno::ENODEV,
+            ))))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn rdwr(&mut self, msgs: &mut Vec<Msg>) -> Result<i32, BusError> {
+        self.check_removed()?;
+        self.backend.rdwr(msgs)
     }
 
     // Writes a single byte value to the I2C device with the specified address
     // and to the register.  Is constructed as follows:
     // 1st byte: addr << 1
-    // 2nd byte: reg
-    // 3rd byte: value
-    pub(crate) fn write_reg(&mut self, addr: u16, reg: u8, value: u8) -> Result<i32, BusError> {
+    // 2nd byte(s): reg pointer
+    // Next byte: value
+    pub(crate) fn write_reg(&mut self, addr: u16, reg: RegPointer, value: u8) -> Result<i32, BusError> {
+        self.write_reg_bytes(addr, reg, &[value])
+    }
+
+    // Writes multiple byte values to the I2C device with the specified
+    // address, starting at the register.  Is constructed as follows:
+    // 1st byte: addr << 1
+    // 2nd byte(s): reg pointer
+    // Subsequent bytes: values
+    pub(crate) fn write_reg_bytes(&mut self, addr: u16, reg: RegPointer, values: &[u8]) -> Result<i32, BusError> {
         // Build the message
-        let mut buf: Vec<u8> = vec![reg, value];
+        let mut buf: Vec<u8> = reg.bytes();
+        buf.extend_from_slice(values);
         self.write_bytes(addr, &mut buf)
     }
 
@@ -53,11 +436,11 @@ impl BusInfo {
     // 2nd and subsequent byte: values
     pub(crate) fn write_bytes(&mut self, addr: u16, values: &mut Vec<u8>) -> Result<i32, BusError> {
         // Build the message
-        let msg = I2CMsg::new(addr, values);
+        let msg = Msg::new(addr, values);
         let mut msgs = vec![msg];
 
         // Send it
-        self.rdwr(&mut msgs).map_err(From::from)
+        self.rdwr(&mut msgs)
     }
 
     // Reads from a particular register
@@ -66,23 +449,23 @@ impl BusInfo {
     pub(crate) fn read_reg(
         &mut self,
         addr: u16,
-        reg: u8,
+        reg: RegPointer,
         values: &mut Vec<u8>,
     ) -> Result<i32, BusError> {
         // Need to write the reg then read
 
         // Build the write message
-        let mut bufw: Vec<u8> = vec![reg];
-        let msgw = I2CMsg::new(addr, &mut bufw);
+        let mut bufw: Vec<u8> = reg.bytes();
+        let msgw = Msg::new(addr, &mut bufw);
 
         // Build the read message
-        let mut msgr = I2CMsg::new(addr, values);
+        let mut msgr = Msg::new(addr, values);
         msgr.set_read();
 
         let mut msgs = vec![msgw, msgr];
 
         // Send it
-        self.rdwr(&mut msgs).map_err(From::from)
+        self.rdwr(&mut msgs)
     }
 
     // Just peforms a read
@@ -90,13 +473,234 @@ impl BusInfo {
     // - Then read from it
     pub(crate) fn read_bytes(&mut self, addr: u16, values: &mut Vec<u8>) -> Result<i32, BusError> {
         // Build the read message
-        let mut msgr = I2CMsg::new(addr, values);
+        let mut msgr = Msg::new(addr, values);
         msgr.set_read();
 
         let mut msgs = vec![msgr];
 
         // Send it
-        self.rdwr(&mut msgs).map_err(From::from)
+        self.rdwr(&mut msgs)
+    }
+
+    // Carries out an SMBus operation, via the kernel's SMBus path rather than
+    // I2C_RDWR, so works with SMBus-only adapters
+    pub(crate) fn smbus(&mut self, addr: u16, op: &SmbusOp) -> Result<SmbusData, BusError> {
+        self.check_removed()?;
+        self.backend.smbus(addr, op)
+    }
+
+    // Issues the segments as a single combined transaction, with a repeated
+    // start between each segment.  The data read is returned in each read
+    // segment's buffer.  Wait segments split the transaction: the segments
+    // before one are issued, then the device polled until it ACKs, before
+    // the segments after it.
+    pub(crate) fn transaction(&mut self, segments: &mut [Segment]) -> Result<i32, BusError> {
+        let mut rc = 0;
+        let mut start = 0;
+        for i in 0..=segments.len() {
+            let wait = segments.get(i).map(|segment| (segment.addr, segment.wait));
+            if let Some((_, None)) = wait {
+                continue;
+            }
+            if start < i {
+                rc += self.combined(&mut segments[start..i])?;
+            }
+            if let Some((addr, Some(wait))) = wait {
+                self.poll_ack(addr, wait.mode, wait.timeout, wait.interval)?;
+            }
+            start = i + 1;
+        }
+        Ok(rc)
+    }
+
+    fn combined(&mut self, segments: &mut [Segment]) -> Result<i32, BusError> {
+        let mut msgs = segments
+            .iter_mut()
+            .map(|segment| {
+                let mut msg = Msg::new(segment.addr, &mut segment.data);
+                if segment.read {
+                    msg.set_read();
+                }
+                msg
+            })
+            .collect::<Vec<Msg>>();
+        self.rdwr(&mut msgs)
+    }
+
+    // Performs a zero length write, which checks whether the device ACKs its
+    // address without sending it any data
+    pub(crate) fn write_quick(&mut self, addr: u16) -> Result<i32, BusError> {
+        let mut buf: Vec<u8> = vec![];
+        self.write_bytes(addr, &mut buf)
+    }
+
+    // Returns whether a device ACKs the address, using the given probe mode
+    pub(crate) fn probe(&mut self, addr: u16, mode: ProbeMode) -> bool {
+        let read = match mode {
+            ProbeMode::Auto => (0x30..=0x37).contains(&addr) || (0x50..=0x5f).contains(&addr),
+            ProbeMode::Quick => false,
+            ProbeMode::Read => true,
+        };
+        let rc = if read {
+            let mut buf: Vec<u8> = vec![0; 1];
+            self.read_bytes(addr, &mut buf)
+        } else {
+            self.write_quick(addr)
+        };
+        rc.is_ok()
+    }
+
+    // Probes the device every interval until it ACKs its address, as devices
+    // such as EEPROMs don't while completing a write cycle, returning how long
+    // that took, or ETIMEDOUT if it didn't ACK within the timeout
+    pub(crate) fn poll_ack(
+        &mut self,
+        addr: u16,
+        mode: ProbeMode,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<Duration, BusError> {
+        let start = Instant::now();
+        loop {
+            self.check_removed()?;
+            if self.probe(addr, mode) {
+                return Ok(start.elapsed());
+            }
+            if start.elapsed() >= timeout {
+                return Err(BusError::from(LinuxI2CError::from(nix::Error::Sys(
+                    nix::errno::Errno::ETIMEDOUT,
+                ))));
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    // Returns whether a kernel driver has bound to the device at this
+    // address, in which case i2cdetect reports UU rather than probing it.
+    // sysfs names 10-bit devices using the same ADDR_TEN_BIT offset.
+    pub(crate) fn is_claimed(&self, addr: u16) -> bool {
+        let path = format!("{}{}-{:04x}/driver", SYSFS_I2C_DEVICES_DIR, self.info.id, addr);
+        Path::new(&path).exists()
+    }
+
+    // Probes each address in the range with the mode given for it, skipping
+    // any claimed by a kernel driver.  Returns the addresses which responded,
+    // and those skipped.
+    pub(crate) fn scan<F>(&mut self, first: u16, last: u16, mode: F) -> (Vec<u16>, Vec<u16>)
+    where
+        F: Fn(u16) -> ProbeMode,
+    {
+        let mut found: Vec<u16> = Vec::new();
+        let mut claimed: Vec<u16> = Vec::new();
+        for addr in first..=last {
+            if self.is_claimed(addr) {
+                claimed.push(addr);
+            } else if self.probe(addr, mode(addr)) {
+                found.push(addr);
+            }
+        }
+        (found, claimed)
+    }
+
+    // Reads a register, modifies its value and writes it back, returning the
+    // values before and after.  As the bus is locked, no other request can
+    // access the bus between the read and the write.  The register is
+    // written even if its value is unchanged.
+    pub(crate) fn modify_reg(&mut self, addr: u16, reg: RegPointer, op: ModifyOp) -> Result<(u8, u8), BusError> {
+        let mut buf: Vec<u8> = vec![0; 1];
+        self.read_reg(addr, reg, &mut buf)?;
+        let before = buf[0];
+        let after = op.apply(before);
+        self.write_reg(addr, reg, after)?;
+        Ok((before, after))
+    }
+
+    // Reads values.len() bytes starting at reg, a chunk at a time, returning
+    // the number of chunks.  The bus stays locked throughout, so other
+    // requests can't access the device part way through.
+    pub(crate) fn read_reg_chunked(
+        &mut self,
+        addr: u16,
+        reg: RegPointer,
+        values: &mut [u8],
+        chunking: Chunking,
+    ) -> Result<usize, ChunkError> {
+        let mut offset = 0;
+        let mut chunks = 0;
+        while offset < values.len() {
+            let len = std::cmp::min(chunking.len, values.len() - offset);
+            let mut buf: Vec<u8> = vec![0; len];
+            self.read_reg(addr, chunking.pointer(reg, offset), &mut buf)
+                .map_err(|error| ChunkError { offset, error })?;
+            values[offset..offset + len].copy_from_slice(&buf);
+            offset += len;
+            chunks += 1;
+        }
+        Ok(chunks)
+    }
+
+    // Writes values starting at reg, a chunk at a time, returning the number
+    // of chunks.  Each chunk is a separate write, starting with the register
+    // pointer.
+    pub(crate) fn write_reg_chunked(
+        &mut self,
+        addr: u16,
+        reg: RegPointer,
+        values: &[u8],
+        chunking: Chunking,
+    ) -> Result<usize, ChunkError> {
+        let mut offset = 0;
+        let mut chunks = 0;
+        while offset < values.len() {
+            let pointer = chunking.pointer(reg, offset);
+            let mut len = std::cmp::min(chunking.len - reg.width, values.len() - offset);
+            if let Some(page) = chunking.page {
+                len = std::cmp::min(len, (page - pointer.reg % page) as usize);
+            }
+            self.write_reg_bytes(addr, pointer, &values[offset..offset + len])
+                .map_err(|error| ChunkError { offset, error })?;
+            offset += len;
+            chunks += 1;
+        }
+        Ok(chunks)
+    }
+
+    // Reads registers first to last inclusive, using the given access mode.
+    // Registers which couldn't be read are returned as None, like i2cdump's
+    // XX, but if no registers could be read at all the error is returned.
+    pub(crate) fn dump(
+        &mut self,
+        addr: u16,
+        first: u8,
+        last: u8,
+        mode: DumpMode,
+    ) -> Result<Vec<Option<u8>>, BusError> {
+        // Max length of an SMBus block, and so what i2cdump i mode uses
+        const BLOCK_LEN: usize = 32;
+        let width: usize = match mode {
+            DumpMode::Byte => 1,
+            DumpMode::Word => 2,
+            DumpMode::Block => BLOCK_LEN,
+        };
+        let mut values: Vec<Option<u8>> = Vec::with_capacity(last as usize - first as usize + 1);
+        let mut last_err: Option<BusError> = None;
+        let mut reg = first as usize;
+        while reg <= last as usize {
+            let len = std::cmp::min(width, last as usize + 1 - reg);
+            let mut buf: Vec<u8> = vec![0; len];
+            match self.read_reg(addr, RegPointer::byte(reg as u8), &mut buf) {
+                Ok(_) => values.extend(buf.into_iter().map(Some)),
+                Err(e) => {
+                    values.resize(values.len() + len, None);
+                    last_err = Some(e);
+                }
+            }
+            reg += len;
+        }
+        match last_err {
+            Some(e) if values.iter().all(Option::is_none) => Err(e),
+            _ => Ok(values),
+        }
     }
 }
 
@@ -142,25 +746,68 @@ impl From<i2cdev2::linux::LinuxI2CError> for BusError {
     }
 }
 
-/// Returns an ID and path for each I2C bus found on the system, using the
-/// provided directory and I2C bus prefix string
+// Directory containing an entry for each I2C adapter, each with a name file
+const SYSFS_I2C_DEV_DIR: &str = "/sys/class/i2c-dev/";
+
+// Directory containing an entry for each device the kernel knows about, of
+// the form <bus>-<addr>, with a driver link if a driver has bound to it
+const SYSFS_I2C_DEVICES_DIR: &str = "/sys/bus/i2c/devices/";
+
+// Returns the adapter name for the bus with the given ID from sysfs, as
+// reported by i2cdetect -l
+fn adapter_name(id: usize) -> Option<String> {
+    let path = format!("{}i2c-{}/name", SYSFS_I2C_DEV_DIR, id);
+    match fs::read_to_string(&path) {
+        Ok(name) => Some(name.trim().to_string()),
+        Err(e) => {
+            debug!("Couldn't read adapter name from {} {}", path, e);
+            None
+        }
+    }
+}
+
+// Returns the adapter number from a bus filename, i.e. its trailing digits
+fn adapter_number(f: &str) -> Option<usize> {
+    let num = &f[f.trim_end_matches(|c: char| c.is_ascii_digit()).len()..];
+    num.parse::<usize>().ok()
+}
+
+/// Returns an ID, path and name for each I2C bus found on the system, using
+/// the provided directory, and only including paths for which include
+/// returns true.  The ID is the kernel's adapter number taken from the
+/// filename, so is stable across reboots, whatever order the directory is
+/// read in.  Buses are returned sorted by ID.  Any bus in existing which is
+/// still present is returned as is, rather than being reopened.
 const MAX_BUSES: usize = 127;
-pub(crate) fn init_buses(dir_str: &str, prefix_str: &str) -> Result<Vec<BusInfo>, BusError> {
-    let mut id: usize = 0;
-    let mut buses: Vec<BusInfo> = Vec::new();
+pub(crate) fn init_buses(
+    dir_str: &str,
+    include: &dyn Fn(&str) -> bool,
+    existing: &[Arc<BusInfo>],
+) -> Result<Vec<Arc<BusInfo>>, BusError> {
+    let mut buses: Vec<Arc<BusInfo>> = Vec::new();
     let dir = read_dir(dir_str);
     if let Ok(dir) = dir {
         for entry in dir {
             if let Ok(entry) = entry {
                 if let Some(f) = entry.path().file_name() {
                     if let Some(f) = f.to_str() {
-                        if f.starts_with(prefix_str) {
-                            let path = format!("{}{}", dir_str, f.to_string());
-                            match BusInfo::new(id, path.clone()) {
+                        let path = Path::new(dir_str).join(f).to_string_lossy().to_string();
+                        if include(&path) {
+                            let id = match adapter_number(f) {
+                                Some(id) => id,
+                                None => {
+                                    println!("Ignoring I2C bus {} - no adapter number", path);
+                                    continue;
+                                }
+                            };
+                            let bus = match existing.iter().find(|bus| bus.path == path) {
+                                Some(bus) => Ok(bus.clone()),
+                                None => BusInfo::new(id, path.clone(), adapter_name(id)).map(Arc::new),
+                            };
+                            match bus {
                                 Ok(bus) => {
                                     buses.push(bus);
-                                    id += 1;
-                                    if id >= MAX_BUSES {
+                                    if buses.len() >= MAX_BUSES {
                                         println!("Stopped searching for buses - have hit max");
                                         break;
                                     }
@@ -173,6 +820,277 @@ pub(crate) fn init_buses(dir_str: &str, prefix_str: &str) -> Result<Vec<BusInfo>
             }
         }
     }
+    buses.sort_by_key(|bus| bus.id);
 
     Ok(buses)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::sim::{SimBus, SimDevice};
+    use super::*;
+    use nix::errno::Errno;
+
+    // A bus ID with no devices in sysfs, so no address is claimed
+    const BUS_ID: usize = 9999;
+
+    fn sim_bus(devices: &[(u16, usize)]) -> BusInfo {
+        let mut bus = SimBus::new();
+        for (addr, size) in devices {
+            bus.add_device(*addr, SimDevice::new(*size));
+        }
+        BusInfo::with_backend(BUS_ID, "sim-test".to_string(), None, Box::new(bus))
+    }
+
+    fn errno(e: &BusError) -> Option<Errno> {
+        match e {
+            BusError::LinuxI2CError(LinuxI2CError::Nix(nix::Error::Sys(e))) => Some(*e),
+            _ => None,
+        }
+    }
+
+    fn pointer(reg: u32, width: usize) -> RegPointer {
+        RegPointer {
+            reg,
+            width,
+            lsb_first: false,
+        }
+    }
+
+    #[test]
+    fn reg_write_then_read() {
+        let info = sim_bus(&[(0x20, 256)]);
+        let mut bus = info.lock();
+        bus.write_reg_bytes(0x20, RegPointer::byte(0x10), &[1, 2, 3]).unwrap();
+        bus.write_reg(0x20, RegPointer::byte(0x13), 4).unwrap();
+        let mut values: Vec<u8> = vec![0; 4];
+        bus.read_reg(0x20, RegPointer::byte(0x10), &mut values).unwrap();
+        assert_eq!(values, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn reg_read_wraps_at_end_of_register_file() {
+        let info = sim_bus(&[(0x20, 256)]);
+        let mut bus = info.lock();
+        bus.write_reg_bytes(0x20, RegPointer::byte(0xff), &[7, 8]).unwrap();
+        let mut values: Vec<u8> = vec![0; 1];
+        bus.read_reg(0x20, RegPointer::byte(0), &mut values).unwrap();
+        assert_eq!(values, vec![8]);
+    }
+
+    #[test]
+    fn two_byte_pointer() {
+        let info = sim_bus(&[(0x50, 4096)]);
+        let mut bus = info.lock();
+        bus.write_reg_bytes(0x50, pointer(0x0123, 2), &[0xaa, 0xbb]).unwrap();
+        let mut values: Vec<u8> = vec![0; 2];
+        bus.read_reg(0x50, pointer(0x0123, 2), &mut values).unwrap();
+        assert_eq!(values, vec![0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn pointer_bytes() {
+        assert_eq!(pointer(0x0123, 2).bytes(), vec![0x01, 0x23]);
+        let lsb_first = RegPointer {
+            lsb_first: true,
+            ..pointer(0x01020304, 4)
+        };
+        assert_eq!(lsb_first.bytes(), vec![4, 3, 2, 1]);
+        assert_eq!(RegPointer::max(1), 0xff);
+        assert_eq!(RegPointer::max(4), 0xffff_ffff);
+    }
+
+    #[test]
+    fn missing_device_nacks() {
+        let info = sim_bus(&[(0x20, 256)]);
+        let mut bus = info.lock();
+        let mut values: Vec<u8> = vec![0; 1];
+        let e = bus.read_reg(0x21, RegPointer::byte(0), &mut values).unwrap_err();
+        assert_eq!(errno(&e), Some(Errno::ENXIO));
+    }
+
+    #[test]
+    fn ten_bit_addresses_are_distinct() {
+        let ten_bit = ADDR_TEN_BIT | 0x50;
+        let info = sim_bus(&[(0x50, 256), (ten_bit, 256)]);
+        let mut bus = info.lock();
+        bus.write_reg(0x50, RegPointer::byte(0), 1).unwrap();
+        bus.write_reg(ten_bit, RegPointer::byte(0), 2).unwrap();
+        let mut values: Vec<u8> = vec![0; 1];
+        bus.read_reg(ten_bit, RegPointer::byte(0), &mut values).unwrap();
+        assert_eq!(values, vec![2]);
+        bus.read_reg(0x50, RegPointer::byte(0), &mut values).unwrap();
+        assert_eq!(values, vec![1]);
+    }
+
+    #[test]
+    fn ten_bit_message_flags() {
+        let mut data: Vec<u8> = vec![];
+        let msg = Msg::new(ADDR_TEN_BIT | 0x150, &mut data);
+        assert_eq!(msg.addr, 0x150);
+        assert_eq!(msg.flags, I2CMsgFlags::I2C_M_TEN.bits());
+        assert_eq!(msg.full_addr(), ADDR_TEN_BIT | 0x150);
+        assert!(!is_ten_bit(0x50));
+    }
+
+    #[test]
+    fn scan_finds_devices() {
+        let info = sim_bus(&[(0x20, 256), (0x50, 256), (0x68, 256)]);
+        let mut bus = info.lock();
+        for mode in &[ProbeMode::Auto, ProbeMode::Quick, ProbeMode::Read] {
+            let (found, claimed) = bus.scan(0x08, 0x77, |_| *mode);
+            assert_eq!(found, vec![0x20, 0x50, 0x68]);
+            assert!(claimed.is_empty());
+        }
+        let (found, _) = bus.scan(0x30, 0x5f, |_| ProbeMode::Auto);
+        assert_eq!(found, vec![0x50]);
+        let (found, _) = bus.scan(0x08, 0x77, |addr| match addr {
+            0x20 => ProbeMode::Read,
+            _ => ProbeMode::Quick,
+        });
+        assert_eq!(found, vec![0x20, 0x50, 0x68]);
+    }
+
+    #[test]
+    fn chunked_read_splits_into_chunks() {
+        let info = sim_bus(&[(0x50, 4096)]);
+        let mut bus = info.lock();
+        let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        bus.write_reg_bytes(0x50, pointer(0, 2), &data).unwrap();
+        let chunking = Chunking {
+            len: 256,
+            page: None,
+            fixed: false,
+        };
+        let mut values: Vec<u8> = vec![0; 1000];
+        let chunks = bus.read_reg_chunked(0x50, pointer(0, 2), &mut values, chunking).unwrap();
+        assert_eq!(chunks, 4);
+        assert_eq!(values, data);
+    }
+
+    #[test]
+    fn chunked_write_leaves_room_for_pointer() {
+        let info = sim_bus(&[(0x50, 4096)]);
+        let mut bus = info.lock();
+        let data: Vec<u8> = (0..20).collect();
+        let chunking = Chunking {
+            len: 7,
+            page: None,
+            fixed: false,
+        };
+        // Each 7 byte message carries a 2 byte pointer and 5 bytes of data
+        let chunks = bus.write_reg_chunked(0x50, pointer(0x100, 2), &data, chunking).unwrap();
+        assert_eq!(chunks, 4);
+        let mut values: Vec<u8> = vec![0; 20];
+        bus.read_reg(0x50, pointer(0x100, 2), &mut values).unwrap();
+        assert_eq!(values, data);
+    }
+
+    #[test]
+    fn chunked_write_stops_at_page_boundaries() {
+        let info = sim_bus(&[(0x50, 256)]);
+        let mut bus = info.lock();
+        let data: Vec<u8> = (0..16).collect();
+        let chunking = Chunking {
+            len: 64,
+            page: Some(8),
+            fixed: false,
+        };
+        // 0x06-0x07, 0x08-0x0f, then 0x10-0x15
+        let chunks = bus.write_reg_chunked(0x50, RegPointer::byte(6), &data, chunking).unwrap();
+        assert_eq!(chunks, 3);
+        let mut values: Vec<u8> = vec![0; 16];
+        bus.read_reg(0x50, RegPointer::byte(6), &mut values).unwrap();
+        assert_eq!(values, data);
+    }
+
+    #[test]
+    fn chunked_write_to_fixed_register() {
+        let info = sim_bus(&[(0x3c, 256)]);
+        let mut bus = info.lock();
+        let chunking = Chunking {
+            len: 3,
+            page: None,
+            fixed: true,
+        };
+        // Every chunk is written to register 0x40, so only the last remains
+        let chunks = bus.write_reg_chunked(0x3c, RegPointer::byte(0x40), &[1, 2, 3, 4, 5], chunking).unwrap();
+        assert_eq!(chunks, 3);
+        let mut values: Vec<u8> = vec![0; 2];
+        bus.read_reg(0x3c, RegPointer::byte(0x40), &mut values).unwrap();
+        assert_eq!(values, vec![5, 4]);
+    }
+
+    #[test]
+    fn chunked_failure_reports_offset() {
+        let info = sim_bus(&[]);
+        let mut bus = info.lock();
+        let chunking = Chunking {
+            len: 4,
+            page: None,
+            fixed: false,
+        };
+        let e = bus.write_reg_chunked(0x50, RegPointer::byte(0), &[0; 8], chunking).unwrap_err();
+        assert_eq!(e.offset, 0);
+        assert_eq!(errno(&e.error), Some(Errno::ENXIO));
+    }
+
+    #[test]
+    fn transaction_with_wait() {
+        let info = sim_bus(&[(0x50, 256)]);
+        let mut bus = info.lock();
+        let wait = AckWait {
+            mode: ProbeMode::Read,
+            timeout: Duration::from_millis(10),
+            interval: Duration::from_millis(1),
+        };
+        let segment = |read, data, wait| Segment {
+            addr: 0x50,
+            read,
+            data,
+            wait,
+        };
+        let mut segments = vec![
+            segment(false, vec![0, 1, 2], None),
+            segment(false, vec![], Some(wait)),
+            segment(false, vec![0], None),
+            segment(true, vec![0; 2], None),
+        ];
+        assert_eq!(bus.transaction(&mut segments).unwrap(), 3);
+        assert_eq!(segments[3].data, vec![1, 2]);
+    }
+
+    #[test]
+    fn poll_ack_times_out() {
+        let info = sim_bus(&[(0x50, 256)]);
+        let mut bus = info.lock();
+        let interval = Duration::from_millis(1);
+        assert!(bus.poll_ack(0x50, ProbeMode::Read, Duration::from_millis(10), interval).is_ok());
+        let e = bus.poll_ack(0x51, ProbeMode::Read, Duration::from_millis(10), interval).unwrap_err();
+        assert_eq!(errno(&e), Some(Errno::ETIMEDOUT));
+    }
+
+    #[test]
+    fn try_lock_for_times_out_while_locked() {
+        let info = sim_bus(&[]);
+        let bus = info.lock();
+        assert!(info.try_lock_for(Duration::from_millis(5)).is_none());
+        drop(bus);
+        assert!(info.try_lock_for(Duration::from_millis(5)).is_some());
+    }
+
+    #[test]
+    fn lock_recovers_from_poisoning() {
+        let info = Arc::new(sim_bus(&[(0x50, 256)]));
+        let poisoner = info.clone();
+        let panicked = thread::spawn(move || {
+            let _bus = poisoner.lock();
+            panic!("request panicked holding the bus");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(info.lock().write_quick(0x50).is_ok());
+        let mut bus = info.try_lock_for(Duration::from_millis(5)).unwrap();
+        assert!(bus.write_quick(0x50).is_ok());
+    }
+}
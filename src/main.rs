@@ -1,6 +1,6 @@
 //! Main binary entry point for openapi_client implementation.
 
-use httpd_util::{get_server_addr, https, init_app, ssl};
+use httpd_util::{get_server_addr, https, init_app};
 use log::{debug};
 
 #[path = "server.rs"] mod server;
@@ -12,21 +12,56 @@ fn main() {
         "i2cbus",
         "Piers Finlayson, piers@piersandkatie.com",
         "An HTTP(S) microservice exposing I2C bus functionality",
-        vec![],
-        vec![],
+        vec![
+            "[I2C_DEV_DIR] - Directory containing I2C buses (default /dev/)",
+            "[I2C_DEV_PATTERN] - Glob pattern I2C bus filenames must match (default i2c-*)",
+            "[I2C_ALLOW] - Comma separated list of the only bus paths to use",
+            "[I2C_DENY] - Comma separated list of bus paths not to use",
+            "[SSL_KEY] - Path to the SSL key (default /ssl/key.pem)",
+            "[SSL_CERT] - Path to the SSL certificate chain (default /ssl/cert.pem)",
+            "[SSL_CLIENT_CA] - Path to CA certificates to verify client certificates with",
+            "[I2C_AUTH_TOKENS] - Path to a file of bearer tokens/API keys, as identity:token lines",
+            "[I2C_AUTH_BASIC] - Path to an htpasswd file of bcrypt hashed Basic credentials",
+            "[I2C_POLICY] - Path to a policy file of the buses, addresses and registers each identity may access",
+            "[I2C_READ_ONLY] - Set to 1 to disable all writes",
+            "[I2C_PROTECT] - Write-protected ranges, as bus:addr[:reg], e.g. \"0:0x50-0x57,1:0x48:0x01-0x03\"",
+            "[I2C_PROFILES] - Directory containing JSON device profiles",
+            "[I2C_DEVICES] - Profiles to bind to devices at startup, as bus:addr=profile, e.g. \"0:0x20=pca9956b\"",
+            "[I2C_SIM] - Use simulated I2C buses, e.g. \"0x20,0x50:128;0x68\"",
+            "[I2C_WORKERS] - Number of threads to run bus operations on (default 8)",
+            "[I2C_TIMEOUT] - Request timeout in milliseconds (default 5000)",
+            "[I2C_MAX_TRANSFER] - Most bytes per message for chunked transfers (default 8192, at least 5)",
+            "[I2C_REQUIRED] - Buses and devices required for readiness, as bus[:addr], e.g. \"1,0:0x50\"",
+        ],
+        vec![
+            server::I2C_DEV_DIR_VAR,
+            server::I2C_DEV_PATTERN_VAR,
+            server::I2C_ALLOW_VAR,
+            server::I2C_DENY_VAR,
+            server::SSL_CLIENT_CA_VAR,
+            server::I2C_AUTH_TOKENS_VAR,
+            server::I2C_AUTH_BASIC_VAR,
+            server::I2C_POLICY_VAR,
+            server::I2C_READ_ONLY_VAR,
+            server::I2C_PROTECT_VAR,
+            server::I2C_PROFILES_VAR,
+            server::I2C_DEVICES_VAR,
+            server::I2C_SIM_VAR,
+            server::I2C_WORKERS_VAR,
+            server::I2C_TIMEOUT_VAR,
+            server::I2C_MAX_TRANSFER_VAR,
+            server::I2C_REQUIRED_VAR,
+        ],
     );
 
-    let ssl = match https() {
-        true => Some(ssl().unwrap()),
-        _ => None,
-    };
-    match ssl {
-        Some(_) => debug!("Using SSL"),
-        None => debug!("Not using SSL"),
+    let https = https();
+    match https {
+        true => debug!("Using SSL"),
+        false => debug!("Not using SSL"),
     }
 
     let addr_socket = get_server_addr();
     let addr_string = format!("{}:{}", addr_socket.ip(), addr_socket.port());
     debug!("Bind to: {}", addr_string);
-    hyper::rt::run(server::create(&addr_string, ssl));
+    hyper::rt::run(server::create(&addr_string, https));
 }
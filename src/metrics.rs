@@ -0,0 +1,276 @@
+//! Bus traffic metrics, served in the Prometheus text format.
+//!
+//! Counts operations, bytes and errors per bus, address and operation, and
+//! keeps histograms of how long operations take and how long they wait for
+//! the bus lock.  Bytes are those sent or received after the address,
+//! including any register (command) bytes but not SMBus block counts.
+
+use lazy_static::lazy_static;
+use nix::errno::Errno;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// Upper bounds, in seconds, of the histogram buckets
+const BUCKETS: &[f64] = &[
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+    5.0,
+];
+
+// Cumulative counts of observations no greater than each of BUCKETS, plus
+// the count and sum of all of them
+struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram {
+            buckets: vec![0; BUCKETS.len()],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, secs: f64) {
+        for (count, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+// Operations are keyed by bus ID, address (None if the operation isn't to a
+// single address, e.g. a scan) and operation name
+type OpKey = (usize, Option<u16>, &'static str);
+
+#[derive(Default)]
+struct Metrics {
+    ops: BTreeMap<OpKey, u64>,
+    bytes_read: BTreeMap<(usize, Option<u16>), u64>,
+    bytes_written: BTreeMap<(usize, Option<u16>), u64>,
+    // Errors are additionally keyed by errno, if there is one
+    errors: BTreeMap<(OpKey, Option<i32>), u64>,
+    latency: BTreeMap<(usize, &'static str), Histogram>,
+    lock_wait: BTreeMap<usize, Histogram>,
+}
+
+lazy_static! {
+    static ref METRICS: Mutex<Metrics> = Mutex::new(Metrics::default());
+}
+
+/// Times an operation on a bus.  Started before locking the bus, so it can
+/// record how long the lock took to get, and finished with the outcome.
+pub(crate) struct Timer {
+    key: OpKey,
+    start: Instant,
+}
+
+impl Timer {
+    pub(crate) fn start(bus: usize, addr: Option<u16>, op: &'static str) -> Timer {
+        Timer {
+            key: (bus, addr, op),
+            start: Instant::now(),
+        }
+    }
+
+    // Records the time spent waiting for the bus lock, and restarts the
+    // timer for the operation itself
+    pub(crate) fn locked(&mut self) {
+        let now = Instant::now();
+        let waited = now.duration_since(self.start).as_secs_f64();
+        self.start = now;
+        METRICS
+            .lock()
+            .unwrap()
+            .lock_wait
+            .entry(self.key.0)
+            .or_insert_with(Histogram::new)
+            .observe(waited);
+    }
+
+    // Records a successful operation, which read and wrote the given numbers
+    // of bytes
+    pub(crate) fn ok(self, read: usize, written: usize) {
+        let mut metrics = self.finish();
+        let (bus, addr, _) = self.key;
+        *metrics.bytes_read.entry((bus, addr)).or_insert(0) += read as u64;
+        *metrics.bytes_written.entry((bus, addr)).or_insert(0) += written as u64;
+    }
+
+    // Records a failed operation, with its errno if known
+    pub(crate) fn failed(self, errno: Option<i32>) {
+        let mut metrics = self.finish();
+        *metrics.errors.entry((self.key, errno)).or_insert(0) += 1;
+    }
+
+    fn finish(&self) -> std::sync::MutexGuard<'static, Metrics> {
+        let secs = self.start.elapsed().as_secs_f64();
+        let mut metrics = METRICS.lock().unwrap();
+        *metrics.ops.entry(self.key).or_insert(0) += 1;
+        let (bus, _, op) = self.key;
+        metrics
+            .latency
+            .entry((bus, op))
+            .or_insert_with(Histogram::new)
+            .observe(secs);
+        metrics
+    }
+}
+
+// Formats labels for a sample, omitting an unknown address
+fn labels(bus: usize, addr: Option<u16>, op: Option<&str>) -> String {
+    let mut labels = format!("bus=\"{}\"", bus);
+    if let Some(addr) = addr {
+        write!(labels, ",addr=\"0x{:02x}\"", addr).unwrap();
+    }
+    if let Some(op) = op {
+        write!(labels, ",op=\"{}\"", op).unwrap();
+    }
+    labels
+}
+
+fn errno_label(errno: Option<i32>) -> String {
+    match errno.map(Errno::from_i32) {
+        Some(Errno::UnknownErrno) => errno.unwrap().to_string(),
+        Some(errno) => format!("{:?}", errno),
+        None => "unknown".to_string(),
+    }
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap();
+    writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn histogram(text: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    for (count, bound) in histogram.buckets.iter().zip(BUCKETS) {
+        writeln!(text, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count).unwrap();
+    }
+    writeln!(text, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, histogram.count).unwrap();
+    writeln!(text, "{}_sum{{{}}} {}", name, labels, histogram.sum).unwrap();
+    writeln!(text, "{}_count{{{}}} {}", name, labels, histogram.count).unwrap();
+}
+
+/// Renders all metrics in the Prometheus text exposition format
+pub(crate) fn render() -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut text = String::new();
+
+    let name = "i2cbus_operations_total";
+    header(&mut text, name, "counter", "Bus operations, by bus, address and operation");
+    for ((bus, addr, op), count) in &metrics.ops {
+        writeln!(text, "{}{{{}}} {}", name, labels(*bus, *addr, Some(op)), count).unwrap();
+    }
+
+    let name = "i2cbus_errors_total";
+    header(&mut text, name, "counter", "Failed bus operations, by bus, address, operation and errno");
+    for (((bus, addr, op), errno), count) in &metrics.errors {
+        let labels = labels(*bus, *addr, Some(op));
+        writeln!(text, "{}{{{},errno=\"{}\"}} {}", name, labels, errno_label(*errno), count).unwrap();
+    }
+
+    let name = "i2cbus_read_bytes_total";
+    header(&mut text, name, "counter", "Bytes read, by bus and address");
+    for ((bus, addr), count) in &metrics.bytes_read {
+        writeln!(text, "{}{{{}}} {}", name, labels(*bus, *addr, None), count).unwrap();
+    }
+
+    let name = "i2cbus_written_bytes_total";
+    header(&mut text, name, "counter", "Bytes written, by bus and address");
+    for ((bus, addr), count) in &metrics.bytes_written {
+        writeln!(text, "{}{{{}}} {}", name, labels(*bus, *addr, None), count).unwrap();
+    }
+
+    let name = "i2cbus_operation_duration_seconds";
+    header(&mut text, name, "histogram", "Time taken by bus operations, once the bus is locked");
+    for ((bus, op), hist) in &metrics.latency {
+        histogram(&mut text, name, &labels(*bus, None, Some(op)), hist);
+    }
+
+    let name = "i2cbus_lock_wait_seconds";
+    header(&mut text, name, "histogram", "Time spent waiting for the bus lock");
+    for (bus, hist) in &metrics.lock_wait {
+        histogram(&mut text, name, &labels(*bus, None, None), hist);
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bus for operations recorded by these tests, so they can be told apart
+    // from those of other tests, which share METRICS
+    const BUS: usize = 9999;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut hist = Histogram::new();
+        for secs in &[0.0002, 0.003, 0.003, 10.0] {
+            hist.observe(*secs);
+        }
+        let bucket = |bound: f64| hist.buckets[BUCKETS.iter().position(|b| *b == bound).unwrap()];
+        assert_eq!(bucket(0.0001), 0);
+        assert_eq!(bucket(0.00025), 1);
+        assert_eq!(bucket(0.0025), 1);
+        assert_eq!(bucket(0.005), 3);
+        assert_eq!(bucket(5.0), 3);
+        assert!(hist.buckets.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(hist.count, 4);
+        assert!((hist.sum - 10.0062).abs() < 1e-9);
+
+        let mut text = String::new();
+        histogram(&mut text, "test", "bus=\"0\"", &hist);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), BUCKETS.len() + 3);
+        assert_eq!(lines[0], "test_bucket{bus=\"0\",le=\"0.0001\"} 0");
+        assert_eq!(lines[BUCKETS.len()], "test_bucket{bus=\"0\",le=\"+Inf\"} 4");
+        assert_eq!(lines[BUCKETS.len() + 2], "test_count{bus=\"0\"} 4");
+    }
+
+    #[test]
+    fn errno_labels() {
+        assert_eq!(errno_label(Some(Errno::EIO as i32)), "EIO");
+        assert_eq!(errno_label(Some(9999)), "9999");
+        assert_eq!(errno_label(None), "unknown");
+    }
+
+    #[test]
+    fn render_labels_samples() {
+        Timer::start(BUS, Some(0x50), "read_bytes").ok(2, 1);
+        Timer::start(BUS, Some(0xa150), "read_bytes").ok(4, 0);
+        Timer::start(BUS, None, "scan").ok(0, 0);
+        Timer::start(BUS, Some(0x50), "write_bytes").failed(Some(Errno::ENXIO as i32));
+        Timer::start(BUS, None, "transaction").failed(None);
+        let mut timer = Timer::start(BUS, Some(0x50), "read_byte");
+        timer.locked();
+        timer.ok(1, 0);
+
+        let text = render();
+        let expected = [
+            "i2cbus_operations_total{bus=\"9999\",addr=\"0x50\",op=\"read_bytes\"} 1",
+            "i2cbus_operations_total{bus=\"9999\",addr=\"0xa150\",op=\"read_bytes\"} 1",
+            "i2cbus_operations_total{bus=\"9999\",op=\"scan\"} 1",
+            "i2cbus_errors_total{bus=\"9999\",addr=\"0x50\",op=\"write_bytes\",errno=\"ENXIO\"} 1",
+            "i2cbus_errors_total{bus=\"9999\",op=\"transaction\",errno=\"unknown\"} 1",
+            "i2cbus_read_bytes_total{bus=\"9999\",addr=\"0x50\"} 3",
+            "i2cbus_read_bytes_total{bus=\"9999\"} 0",
+            "i2cbus_written_bytes_total{bus=\"9999\",addr=\"0x50\"} 1",
+            "i2cbus_operation_duration_seconds_bucket{bus=\"9999\",op=\"read_bytes\",le=\"+Inf\"} 2",
+            "i2cbus_operation_duration_seconds_count{bus=\"9999\",op=\"scan\"} 1",
+            "i2cbus_lock_wait_seconds_count{bus=\"9999\"} 1",
+        ];
+        for line in &expected {
+            assert!(text.lines().any(|l| l == *line), "{} not in\n{}", line, text);
+        }
+        assert!(text.contains("# TYPE i2cbus_operation_duration_seconds histogram\n"));
+    }
+}
@@ -0,0 +1,523 @@
+//! Authorisation policy - which identities may read from or write to which
+//! buses, addresses and registers.
+//!
+//! The policy file has one rule per line, of the form:
+//!
+//!   identity access bus addr reg
+//!
+//! where identity is an authenticated identity (see auth) or * for anyone,
+//! in double quotes if it contains spaces, as client certificate subjects
+//! such as "CN=client, O=Example" do.  access is read, write (which implies
+//! read) or deny, and bus, addr and reg
+//! are each a number, an inclusive range such as 0x20-0x27, or * for any.
+//! Numbers are decimal or 0x prefixed hex.  Blank lines and lines starting
+//! with # are ignored.
+//!
+//! Rules are checked in order.  A deny rule which covers any part of an
+//! operation forbids it, and a read or write rule which covers all of an
+//! operation, with sufficient access, permits it.  The first such rule
+//! decides, and an operation no rule permits is forbidden.
+//!
+//! Independently of the policy, writes may be disabled entirely (read-only
+//! mode), or refused to particular write-protected ranges, for everyone.
+
+use nix::errno::Errno;
+use std::fmt;
+use std::fs;
+use std::io;
+
+// Environment variable which, if set, is the path to the policy file.  If not
+// set, every identity may carry out any operation.
+pub(crate) const I2C_POLICY_VAR: &str = "I2C_POLICY";
+
+// Environment variable which, if set to 1, true or yes, disables all writes
+pub(crate) const I2C_READ_ONLY_VAR: &str = "I2C_READ_ONLY";
+
+// Environment variable which, if set, is a comma separated list of
+// write-protected ranges, each of the form bus:addr[:reg], for example
+// "0:0x50-0x57,1:0x48:0x01-0x03"
+pub(crate) const I2C_PROTECT_VAR: &str = "I2C_PROTECT";
+
+// The access an operation needs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Access {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+// An inclusive range of bus IDs, addresses or registers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Range {
+    pub(crate) first: u32,
+    pub(crate) last: u32,
+}
+
+impl Range {
+    pub(crate) fn new(first: u32, last: u32) -> Range {
+        Range { first, last }
+    }
+
+    pub(crate) fn one(value: u32) -> Range {
+        Range::new(value, value)
+    }
+
+    fn contains(&self, other: &Range) -> bool {
+        self.first <= other.first && other.last <= self.last
+    }
+
+    fn overlaps(&self, other: &Range) -> bool {
+        self.first <= other.last && other.first <= self.last
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.first == self.last {
+            write!(f, "0x{:02x}", self.first)
+        } else {
+            write!(f, "0x{:02x}-0x{:02x}", self.first, self.last)
+        }
+    }
+}
+
+// What an operation touches.  The registers aren't known for raw reads and
+// writes, where the device's register pointer (if it has one) is used or set
+// by the data itself, so they can only be permitted by rules covering any
+// register.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Target {
+    pub(crate) bus: u32,
+    pub(crate) addrs: Range,
+    pub(crate) regs: Option<Range>,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bus {} addr {}", self.bus, self.addrs)?;
+        match &self.regs {
+            Some(regs) => write!(f, " reg {}", regs),
+            None => write!(f, " (raw)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleAccess {
+    Read,
+    Write,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    // Line number in the policy file, and the line itself, to report which
+    // rule forbade an operation
+    line_num: usize,
+    line: String,
+    identity: Option<String>,
+    access: RuleAccess,
+    buses: Option<Range>,
+    addrs: Option<Range>,
+    regs: Option<Range>,
+}
+
+// Whether a rule's range covers an operation's.  None is any (for the rule) or
+// unknown (for the operation).  Deny rules only need to overlap, and treat
+// unknown as a match, while other rules need to contain the operation's range.
+fn covers(rule: &Option<Range>, target: &Option<Range>, deny: bool) -> bool {
+    match (rule, target) {
+        (None, _) => true,
+        (Some(_), None) => deny,
+        (Some(rule), Some(target)) if deny => rule.overlaps(target),
+        (Some(rule), Some(target)) => rule.contains(target),
+    }
+}
+
+impl Rule {
+    fn matches(&self, identity: &str, target: &Target) -> bool {
+        let deny = self.access == RuleAccess::Deny;
+        let identity_matches = match &self.identity {
+            Some(id) => id == identity,
+            None => true,
+        };
+        identity_matches
+            && covers(&self.buses, &Some(Range::one(target.bus)), deny)
+            && covers(&self.addrs, &Some(target.addrs), deny)
+            && covers(&self.regs, &target.regs, deny)
+    }
+}
+
+/// Why an operation was forbidden, with the rule which forbade it, if any.
+/// error is EACCES if the policy forbade it, or EROFS if it's a write and
+/// writes are disabled or the target is write-protected.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Violation {
+    pub(crate) error: i32,
+    pub(crate) description: String,
+    pub(crate) rule: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Policy {
+    path: String,
+    rules: Vec<Rule>,
+}
+
+impl Policy {
+    pub(crate) fn load(path: &str) -> io::Result<Policy> {
+        Policy::parse(path, &fs::read_to_string(path)?)
+    }
+
+    // Parses the policy in text, loaded from path
+    pub(crate) fn parse(path: &str, text: &str) -> io::Result<Policy> {
+        let mut rules = Vec::new();
+        for (num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_rule(num + 1, line) {
+                Some(rule) => rules.push(rule),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid rule at line {} of {}: {}", num + 1, path, line),
+                    ))
+                }
+            }
+        }
+        Ok(Policy {
+            path: path.to_string(),
+            rules,
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    // Checks whether identity may carry out an operation needing access to
+    // target
+    pub(crate) fn check(&self, identity: &str, access: Access, target: &Target) -> Result<(), Violation> {
+        let operation = format!("{} {} {}", identity, access, target);
+        for rule in self.rules.iter().filter(|rule| rule.matches(identity, target)) {
+            match (rule.access, access) {
+                (RuleAccess::Deny, _) => {
+                    return Err(Violation {
+                        error: Errno::EACCES as i32,
+                        description: format!("Forbidden: {}", operation),
+                        rule: Some(format!("{}:{}: {}", self.path, rule.line_num, rule.line)),
+                    })
+                }
+                (RuleAccess::Read, Access::Read) | (RuleAccess::Write, _) => return Ok(()),
+                (RuleAccess::Read, Access::Write) => continue,
+            }
+        }
+        Err(Violation {
+            error: Errno::EACCES as i32,
+            description: format!("Forbidden: {} (no rule permits it)", operation),
+            rule: None,
+        })
+    }
+}
+
+// A write-protected range
+#[derive(Debug, Clone, PartialEq)]
+struct Protected {
+    spec: String,
+    buses: Option<Range>,
+    addrs: Option<Range>,
+    regs: Option<Range>,
+}
+
+/// Read-only mode and write-protected ranges, which apply to every identity
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct WriteProtect {
+    read_only: bool,
+    protected: Vec<Protected>,
+}
+
+impl WriteProtect {
+    // Parses the list of protected ranges, as described for I2C_PROTECT
+    pub(crate) fn new(read_only: bool, spec: &str) -> Result<WriteProtect, String> {
+        let mut protected = Vec::new();
+        for range in spec.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let mut fields = range.split(':');
+            let parsed = (
+                fields.next().and_then(parse_range),
+                fields.next().and_then(parse_range),
+                fields.next().map_or(Some(None), parse_range),
+                fields.next(),
+            );
+            match parsed {
+                (Some(buses), Some(addrs), Some(regs), None) => protected.push(Protected {
+                    spec: range.to_string(),
+                    buses,
+                    addrs,
+                    regs,
+                }),
+                _ => return Err(format!("Invalid write-protected range {}", range)),
+            }
+        }
+        Ok(WriteProtect {
+            read_only,
+            protected,
+        })
+    }
+
+    pub(crate) fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.protected.len()
+    }
+
+    // The violation for any write in read-only mode
+    pub(crate) fn read_only_violation() -> Violation {
+        Violation {
+            error: Errno::EROFS as i32,
+            description: "Writes are disabled (read-only mode)".to_string(),
+            rule: Some(I2C_READ_ONLY_VAR.to_string()),
+        }
+    }
+
+    // Checks a write to target is allowed.  As for deny rules, a protected
+    // range covering any part of the target, or a target whose registers
+    // aren't known, refuses the write.
+    pub(crate) fn check(&self, target: &Target) -> Result<(), Violation> {
+        if self.read_only {
+            return Err(WriteProtect::read_only_violation());
+        }
+        let bus = Some(Range::one(target.bus));
+        let addrs = Some(target.addrs);
+        match self.protected.iter().find(|p| {
+            covers(&p.buses, &bus, true)
+                && covers(&p.addrs, &addrs, true)
+                && covers(&p.regs, &target.regs, true)
+        }) {
+            Some(protected) => Err(Violation {
+                error: Errno::EROFS as i32,
+                description: format!("Write-protected: {}", target),
+                rule: Some(format!("{}: {}", I2C_PROTECT_VAR, protected.spec)),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn parse_num(num: &str) -> Option<u32> {
+    if num.starts_with("0x") || num.starts_with("0X") {
+        u32::from_str_radix(&num[2..], 16).ok()
+    } else {
+        num.parse::<u32>().ok()
+    }
+}
+
+// Parses *, a number or a range, returning Some(None) for *, and None if
+// invalid
+fn parse_range(range: &str) -> Option<Option<Range>> {
+    if range == "*" {
+        return Some(None);
+    }
+    let mut parts = range.splitn(2, '-');
+    let first = parts.next().and_then(parse_num)?;
+    let last = match parts.next() {
+        Some(last) => parse_num(last)?,
+        None => first,
+    };
+    if first <= last {
+        Some(Some(Range::new(first, last)))
+    } else {
+        None
+    }
+}
+
+// Splits the identity, which may be quoted, from the start of a rule,
+// returning the identity (None for *), the identity as written, and the rest
+// of the rule
+fn split_identity(line: &str) -> Option<(Option<String>, &str, &str)> {
+    if let Some(quoted) = line.strip_prefix('"') {
+        let end = quoted.find('"')?;
+        let rest = &quoted[end + 1..];
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        return Some((Some(quoted[..end].to_string()), &line[..end + 2], rest));
+    }
+    let end = line.find(char::is_whitespace)?;
+    let identity = match &line[..end] {
+        "*" => None,
+        identity => Some(identity.to_string()),
+    };
+    Some((identity, &line[..end], &line[end..]))
+}
+
+fn parse_rule(line_num: usize, line: &str) -> Option<Rule> {
+    let (identity, written, rest) = split_identity(line)?;
+    let mut fields: Vec<&str> = vec![written];
+    fields.extend(rest.split_whitespace());
+    if fields.len() != 5 {
+        return None;
+    }
+    let access = match fields[1] {
+        "read" => RuleAccess::Read,
+        "write" => RuleAccess::Write,
+        "deny" => RuleAccess::Deny,
+        _ => return None,
+    };
+    let buses = parse_range(fields[2])?;
+    let addrs = parse_range(fields[3])?;
+    let regs = parse_range(fields[4])?;
+    Some(Rule {
+        line_num,
+        line: fields.join(" "),
+        identity,
+        access,
+        buses,
+        addrs,
+        regs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = "
+# Nobody may use the general call address
+*      deny   *  0x00       *
+alice  write  0  0x20       0x00-0x0f
+alice  read   0  *          *
+\"CN=monitor, O=Example\"  read  1  0x48-0x4f  *
+bob    write  *  *          *
+";
+
+    fn policy() -> Policy {
+        Policy::parse("policy", POLICY).unwrap()
+    }
+
+    fn target(bus: u32, addr: u32, regs: Option<Range>) -> Target {
+        Target {
+            bus,
+            addrs: Range::one(addr),
+            regs,
+        }
+    }
+
+    #[test]
+    fn parse_rules() {
+        let policy = policy();
+        assert_eq!(policy.len(), 5);
+        let rule = &policy.rules[3];
+        assert_eq!(rule.line_num, 6);
+        assert_eq!(rule.identity.as_deref(), Some("CN=monitor, O=Example"));
+        assert_eq!(rule.addrs, Some(Range::new(0x48, 0x4f)));
+        assert_eq!(rule.line, "\"CN=monitor, O=Example\" read 1 0x48-0x4f *");
+        assert_eq!(policy.rules[0].identity, None);
+    }
+
+    #[test]
+    fn parse_invalid_rules() {
+        for line in &[
+            "alice write 0 0x20",
+            "alice write 0 0x20 * extra",
+            "alice maybe 0 0x20 *",
+            "alice write 0 0x27-0x20 *",
+            "alice write 0 0xzz *",
+            "\"CN=monitor, O=Example read 1 * *",
+            "\"CN=monitor\"read 1 * *",
+        ] {
+            assert!(Policy::parse("policy", line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("*"), Some(None));
+        assert_eq!(parse_range("0x10-0x1f"), Some(Some(Range::new(0x10, 0x1f))));
+        assert_eq!(parse_range("7"), Some(Some(Range::one(7))));
+        assert_eq!(parse_range("-1"), None);
+        assert!(Range::new(0, 0x0f).contains(&Range::new(2, 3)));
+        assert!(!Range::new(0, 0x0f).contains(&Range::new(0x0f, 0x10)));
+        assert!(Range::new(0, 0x0f).overlaps(&Range::new(0x0f, 0x10)));
+    }
+
+    #[test]
+    fn write_within_rule() {
+        let policy = policy();
+        let regs = Some(Range::new(0x00, 0x0f));
+        assert!(policy.check("alice", Access::Write, &target(0, 0x20, regs)).is_ok());
+    }
+
+    #[test]
+    fn write_beyond_rule_falls_through_to_read() {
+        let policy = policy();
+        let e = policy.check("alice", Access::Write, &target(0, 0x20, Some(Range::new(0x0f, 0x10))));
+        assert_eq!(e.unwrap_err().rule, None);
+        assert!(policy.check("alice", Access::Read, &target(0, 0x20, Some(Range::new(0x0f, 0x10)))).is_ok());
+    }
+
+    #[test]
+    fn raw_access_needs_any_register() {
+        let policy = policy();
+        assert!(policy.check("alice", Access::Write, &target(0, 0x20, None)).is_err());
+        assert!(policy.check("alice", Access::Read, &target(0, 0x20, None)).is_ok());
+    }
+
+    #[test]
+    fn deny_applies_to_everyone() {
+        let policy = policy();
+        let e = policy.check("bob", Access::Write, &target(0, 0x00, None)).unwrap_err();
+        assert_eq!(e.error, Errno::EACCES as i32);
+        assert_eq!(e.rule.as_deref(), Some("policy:3: * deny * 0x00 *"));
+        // A deny rule only needs to overlap the operation
+        let target = Target {
+            bus: 0,
+            addrs: Range::new(0x00, 0x7f),
+            regs: None,
+        };
+        assert!(policy.check("bob", Access::Read, &target).is_err());
+        assert!(policy.check("bob", Access::Write, &self::target(1, 0x50, None)).is_ok());
+    }
+
+    #[test]
+    fn quoted_identity_matches() {
+        let policy = policy();
+        let monitor = "CN=monitor, O=Example";
+        assert!(policy.check(monitor, Access::Read, &target(1, 0x48, Some(Range::one(0)))).is_ok());
+        assert!(policy.check(monitor, Access::Write, &target(1, 0x48, Some(Range::one(0)))).is_err());
+        assert!(policy.check(monitor, Access::Read, &target(1, 0x50, Some(Range::one(0)))).is_err());
+        assert!(policy.check("CN=monitor", Access::Read, &target(1, 0x48, Some(Range::one(0)))).is_err());
+    }
+
+    #[test]
+    fn unknown_identity_forbidden() {
+        let e = policy().check("mallory", Access::Read, &target(0, 0x20, None)).unwrap_err();
+        assert_eq!(e.rule, None);
+        assert!(e.description.contains("no rule permits it"));
+    }
+
+    #[test]
+    fn write_protect() {
+        let protect = WriteProtect::new(false, "0:0x50-0x57:0x00-0x7f, 1:0x48").unwrap();
+        assert_eq!(protect.len(), 2);
+        let e = protect.check(&target(0, 0x50, Some(Range::new(0x70, 0x80)))).unwrap_err();
+        assert_eq!(e.error, Errno::EROFS as i32);
+        assert!(protect.check(&target(0, 0x50, Some(Range::new(0x80, 0x8f)))).is_ok());
+        assert!(protect.check(&target(0, 0x50, None)).is_err());
+        assert!(protect.check(&target(1, 0x48, Some(Range::one(0x10)))).is_err());
+        assert!(protect.check(&target(1, 0x49, None)).is_ok());
+        assert!(WriteProtect::new(false, "0:0x50:1:2").is_err());
+        assert!(WriteProtect::new(true, "").unwrap().check(&target(0, 0x50, None)).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//! Device profiles - register maps for types of device, so their registers
+//! can be read and written by name, with values decoded into bitfields and
+//! writes validated against the register's width and access.
+//!
+//! Each profile is a JSON file in the profiles directory, of the form:
+//!
+//!   {
+//!     "name": "pca9956b",
+//!     "description": "24-channel constant current LED driver",
+//!     "registers": [
+//!       {"name": "MODE1", "reg": 0, "fields": [{"name": "SLEEP", "lsb": 4}]},
+//!       {"name": "EFLAG0", "reg": 65, "access": "ro"}
+//!     ]
+//!   }
+//!
+//! A register's width is in bytes - 1 (the default), 2 or 4 - and a
+//! multi-byte register is little (the default, as for SMBus words) or big
+//! endian.  Access is rw (the default), ro or wo.  Each field is width bits
+//! (default 1) starting at bit lsb.
+//!
+//! Devices with more than 256 registers take a wider register pointer: the
+//! profile's pointerWidth is 1 (the default), 2 or 4 bytes, sent big (the
+//! default) or little endian according to pointerEndian.
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Environment variable which, if set, is the directory containing profiles
+pub(crate) const I2C_PROFILES_VAR: &str = "I2C_PROFILES";
+
+// Environment variable which, if set, is a comma separated list of devices
+// to bind profiles to at startup, each of the form bus:addr=profile, e.g.
+// "0:0x20=pca9956b"
+pub(crate) const I2C_DEVICES_VAR: &str = "I2C_DEVICES";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Endian {
+    #[default]
+    #[serde(rename = "little")]
+    Little,
+    #[serde(rename = "big")]
+    Big,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum RegAccess {
+    #[default]
+    #[serde(rename = "rw")]
+    ReadWrite,
+    #[serde(rename = "ro")]
+    ReadOnly,
+    #[serde(rename = "wo")]
+    WriteOnly,
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn big() -> Endian {
+    Endian::Big
+}
+
+// A bitfield within a register
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Field {
+    pub(crate) name: String,
+    pub(crate) lsb: u32,
+    #[serde(default = "one")]
+    pub(crate) width: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+impl Field {
+    pub(crate) fn max(&self) -> u32 {
+        mask(self.width)
+    }
+
+    // Returns this field's value within the register value
+    pub(crate) fn get(&self, value: u32) -> u32 {
+        (value >> self.lsb) & self.max()
+    }
+
+    // Returns the register value with this field set to field
+    pub(crate) fn set(&self, value: u32, field: u32) -> u32 {
+        (value & !(self.max() << self.lsb)) | ((field & self.max()) << self.lsb)
+    }
+}
+
+fn mask(bits: u32) -> u32 {
+    match bits {
+        32 => u32::MAX,
+        bits => (1 << bits) - 1,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Register {
+    pub(crate) name: String,
+    pub(crate) reg: u32,
+    #[serde(default = "one")]
+    pub(crate) width: u32,
+    #[serde(default)]
+    pub(crate) endian: Endian,
+    #[serde(default)]
+    pub(crate) access: RegAccess,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) fields: Vec<Field>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+}
+
+impl Register {
+    pub(crate) fn max(&self) -> u32 {
+        mask(self.width * 8)
+    }
+
+    pub(crate) fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    // Combines the bytes read from the register into its value
+    pub(crate) fn decode(&self, bytes: &[u8]) -> u32 {
+        let fold = |value: u32, byte: &u8| (value << 8) | u32::from(*byte);
+        match self.endian {
+            Endian::Big => bytes.iter().fold(0, fold),
+            Endian::Little => bytes.iter().rev().fold(0, fold),
+        }
+    }
+
+    // Splits a value into the bytes to write to the register
+    pub(crate) fn encode(&self, value: u32) -> Vec<u8> {
+        let bytes = (0..self.width).map(|i| (value >> (8 * i)) as u8);
+        match self.endian {
+            Endian::Big => bytes.rev().collect(),
+            Endian::Little => bytes.collect(),
+        }
+    }
+
+    fn validate(&self, max: u32) -> Result<(), String> {
+        if ![1, 2, 4].contains(&self.width) {
+            return Err(format!("register {} width must be 1, 2 or 4", self.name));
+        }
+        if self.reg.checked_add(self.width - 1).filter(|last| *last <= max).is_none() {
+            return Err(format!("register {} extends beyond register {:#x}", self.name, max));
+        }
+        for (i, field) in self.fields.iter().enumerate() {
+            let end = field.lsb.checked_add(field.width).filter(|end| *end <= self.width * 8);
+            if field.width == 0 || end.is_none() {
+                return Err(format!("field {}.{} doesn't fit", self.name, field.name));
+            }
+            if self.fields[..i].iter().any(|other| other.name == field.name) {
+                return Err(format!("field {}.{} is defined twice", self.name, field.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Profile {
+    pub(crate) name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+    #[serde(rename = "pointerWidth")]
+    #[serde(default = "one")]
+    pub(crate) pointer_width: u32,
+    #[serde(rename = "pointerEndian")]
+    #[serde(default = "big")]
+    pub(crate) pointer_endian: Endian,
+    pub(crate) registers: Vec<Register>,
+}
+
+impl Profile {
+    pub(crate) fn register(&self, name: &str) -> Option<&Register> {
+        self.registers.iter().find(|register| register.name == name)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if ![1, 2, 4].contains(&self.pointer_width) {
+            return Err("pointerWidth must be 1, 2 or 4".to_string());
+        }
+        let max = mask(self.pointer_width * 8);
+        for (i, register) in self.registers.iter().enumerate() {
+            register.validate(max)?;
+            if self.registers[..i].iter().any(|other| other.name == register.name) {
+                return Err(format!("register {} is defined twice", register.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Loads every profile (each .json file) in dir, keyed by name
+pub(crate) fn load(dir: &str) -> io::Result<HashMap<String, Profile>> {
+    let mut profiles = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let profile = load_file(&path)?;
+        if profiles.contains_key(&profile.name) {
+            return Err(invalid(&path, format!("profile {} is defined twice", profile.name)));
+        }
+        profiles.insert(profile.name.clone(), profile);
+    }
+    Ok(profiles)
+}
+
+fn load_file(path: &Path) -> io::Result<Profile> {
+    let profile: Profile = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| invalid(path, e.to_string()))?;
+    profile.validate().map_err(|e| invalid(path, e))?;
+    Ok(profile)
+}
+
+fn invalid(path: &Path, error: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid profile {}: {}", path.display(), error),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(json: &str) -> Register {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn decode_encode_little_endian() {
+        let reg = register(r#"{"name": "R", "reg": 0, "width": 4}"#);
+        assert_eq!(reg.endian, Endian::Little);
+        assert_eq!(reg.decode(&[0x78, 0x56, 0x34, 0x12]), 0x1234_5678);
+        assert_eq!(reg.encode(0x1234_5678), vec![0x78, 0x56, 0x34, 0x12]);
+        let reg = register(r#"{"name": "R", "reg": 0, "width": 2}"#);
+        assert_eq!(reg.decode(&[0x34, 0x12]), 0x1234);
+        assert_eq!(reg.encode(0x1234), vec![0x34, 0x12]);
+    }
+
+    #[test]
+    fn decode_encode_big_endian() {
+        let reg = register(r#"{"name": "R", "reg": 0, "width": 4, "endian": "big"}"#);
+        assert_eq!(reg.decode(&[0x12, 0x34, 0x56, 0x78]), 0x1234_5678);
+        assert_eq!(reg.encode(0x1234_5678), vec![0x12, 0x34, 0x56, 0x78]);
+        let reg = register(r#"{"name": "R", "reg": 0, "width": 2, "endian": "big"}"#);
+        assert_eq!(reg.decode(&[0x12, 0x34]), 0x1234);
+        assert_eq!(reg.encode(0x1234), vec![0x12, 0x34]);
+    }
+
+    #[test]
+    fn single_byte_ignores_endian() {
+        for endian in &["little", "big"] {
+            let json = format!(r#"{{"name": "R", "reg": 0, "endian": "{}"}}"#, endian);
+            let reg = register(&json);
+            assert_eq!(reg.decode(&[0xa5]), 0xa5);
+            assert_eq!(reg.encode(0xa5), vec![0xa5]);
+        }
+    }
+
+    #[test]
+    fn field_get_set() {
+        let reg = register(r#"{"name": "R", "reg": 0, "fields": [{"name": "F", "lsb": 2, "width": 3}]}"#);
+        let field = reg.field("F").unwrap();
+        assert_eq!(field.max(), 7);
+        assert_eq!(field.get(0b1011_0100), 0b101);
+        assert_eq!(field.set(0b1111_1111, 0b010), 0b1110_1011);
+        // Values wider than the field are truncated, leaving other bits alone
+        assert_eq!(field.set(0, 0xff), 0b0001_1100);
+        let field = Field {
+            name: "ALL".to_string(),
+            lsb: 0,
+            width: 32,
+            description: None,
+        };
+        assert_eq!(field.get(u32::MAX), u32::MAX);
+        assert_eq!(field.set(0, 0x1234_5678), 0x1234_5678);
+    }
+
+    #[test]
+    fn validate_rejects_invalid_registers() {
+        let invalid = |json: &str| register(json).validate(0xff).unwrap_err();
+        assert!(register(r#"{"name": "R", "reg": 254, "width": 2}"#).validate(0xff).is_ok());
+        assert!(invalid(r#"{"name": "R", "reg": 0, "width": 3}"#).contains("width"));
+        assert!(invalid(r#"{"name": "R", "reg": 255, "width": 2}"#).contains("beyond"));
+        let fields = |fields: &str| format!(r#"{{"name": "R", "reg": 0, "fields": [{}]}}"#, fields);
+        assert!(invalid(&fields(r#"{"name": "F", "lsb": 0, "width": 0}"#)).contains("doesn't fit"));
+        assert!(invalid(&fields(r#"{"name": "F", "lsb": 7, "width": 2}"#)).contains("doesn't fit"));
+        assert!(invalid(&fields(r#"{"name": "F", "lsb": 4294967295, "width": 2}"#)).contains("doesn't fit"));
+        assert!(invalid(&fields(r#"{"name": "F", "lsb": 0}, {"name": "F", "lsb": 1}"#)).contains("twice"));
+    }
+
+    #[test]
+    fn validate_rejects_invalid_profiles() {
+        let profile = |json: &str| serde_json::from_str::<Profile>(json).unwrap().validate();
+        let ok = r#"{"name": "P", "pointerWidth": 2, "registers": [{"name": "R", "reg": 256}]}"#;
+        assert!(profile(ok).is_ok());
+        let narrow = r#"{"name": "P", "registers": [{"name": "R", "reg": 256}]}"#;
+        assert!(profile(narrow).unwrap_err().contains("beyond"));
+        let width = r#"{"name": "P", "pointerWidth": 3, "registers": []}"#;
+        assert!(profile(width).unwrap_err().contains("pointerWidth"));
+        let twice = r#"{"name": "P", "registers": [{"name": "R", "reg": 0}, {"name": "R", "reg": 1}]}"#;
+        assert!(profile(twice).unwrap_err().contains("twice"));
+    }
+}
@@ -8,6 +8,7 @@ mod errors {
 
 pub use self::errors::*;
 
+use arc_swap::ArcSwap;
 use chrono;
 use futures::{future, Future, Stream};
 use hyper::server::conn::Http;
@@ -18,8 +19,8 @@ use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use swagger;
+use swagger::auth::Authorization;
 use swagger::{Has, XSpanIdString};
-use swagger::auth::MakeAllowAllAuthenticator;
 use swagger::EmptyContext;
 use tokio::net::TcpListener;
 
@@ -31,41 +32,73 @@ use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
 
 use i2cbus_api::models;
 
+mod auth;
+mod ext;
 mod http;
+mod policy;
+mod profile;
+mod tls;
+pub(crate) use http::{
+    I2C_ALLOW_VAR, I2C_DENY_VAR, I2C_DEV_DIR_VAR, I2C_DEV_PATTERN_VAR, I2C_MAX_TRANSFER_VAR,
+    I2C_REQUIRED_VAR, I2C_SIM_VAR, I2C_TIMEOUT_VAR, I2C_WORKERS_VAR,
+};
+pub(crate) use auth::{I2C_AUTH_BASIC_VAR, I2C_AUTH_TOKENS_VAR};
+pub(crate) use policy::{I2C_POLICY_VAR, I2C_PROTECT_VAR, I2C_READ_ONLY_VAR};
+pub(crate) use profile::{I2C_DEVICES_VAR, I2C_PROFILES_VAR};
+pub(crate) use tls::SSL_CLIENT_CA_VAR;
 
 #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
-/// Builds an SSL implementation for Simple HTTPS from some hard-coded file names
-pub fn create(addr: &str, ssl: Option<SslAcceptorBuilder>) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+/// Creates the server, using HTTPS if https is set, with the SSL key and
+/// certificates as configured by the environment (see tls::acceptor)
+pub fn create(addr: &str, https: bool) -> Box<dyn Future<Item = (), Error = ()> + Send> {
     let addr = addr.parse().expect("Failed to parse bind address");
 
+    http::watch_buses();
+    http::init_policy();
+    http::init_readiness();
+    http::init_transfers();
+    http::init_profiles();
+
     let server = Server::new();
 
     let service_fn = MakeService::new(server);
 
-    let service_fn = MakeAllowAllAuthenticator::new(service_fn, "cosmo");
+    let auth_methods = auth::methods().expect("Failed to load authentication credentials");
+    let service_fn = auth::MakeAuthenticator::new(service_fn, auth_methods);
 
     let service_fn =
         i2cbus_api::server::context::MakeAddContext::<_, EmptyContext>::new(
             service_fn
         );
 
-    match ssl {
-        Some(ssl) => {
-            let tls_acceptor = ssl.build();
+    match https {
+        true => {
+            let tls_acceptor = tls::acceptor().expect("Failed to load SSL key and certificates");
+            let tls_acceptor = Arc::new(ArcSwap::from_pointee(tls_acceptor));
+            tls::reload_on_sighup(tls_acceptor.clone());
             let service_fn = Arc::new(Mutex::new(service_fn));
             let tls_listener = TcpListener::bind(&addr).unwrap().incoming().for_each(move |tcp| {
                 let addr = tcp.peer_addr().expect("Unable to get remote address");
 
                 let service_fn = service_fn.clone();
 
-                hyper::rt::spawn(tls_acceptor.accept_async(tcp).map_err(|_| ()).and_then(move |tls| {
+                hyper::rt::spawn(tls_acceptor.load().accept_async(tcp).map_err(|_| ()).and_then(move |tls| {
+                    let cert = tls
+                        .get_ref()
+                        .ssl()
+                        .peer_certificate()
+                        .map(|cert| tls::ClientCert::new(cert.subject_name()));
+                    if let Some(cert) = &cert {
+                        info!("Client {} connected with certificate {}", addr, cert.0);
+                    }
+
                     let ms = {
                         let mut service_fn = service_fn.lock().unwrap();
                         service_fn.make_service(&addr)
                     };
 
                     ms.and_then(move |service| {
-                        Http::new().serve_connection(tls, service)
+                        Http::new().serve_connection(tls, tls::AddClientCert::new(service, cert))
                     }).map_err(|_| ())
                 }));
 
@@ -74,7 +107,7 @@ pub fn create(addr: &str, ssl: Option<SslAcceptorBuilder>) -> Box<dyn Future<Ite
 
             Box::new(tls_listener)
         },
-        None => Box::new(hyper::server::Server::bind(&addr).serve(service_fn).map_err(|e| panic!("{:?}", e))),
+        false => Box::new(hyper::server::Server::bind(&addr).serve(service_fn).map_err(|e| panic!("{:?}", e))),
     }
 }
 
@@ -103,11 +136,21 @@ use i2cbus_api::{
     I2cBusWriteBytesResponse,
     I2cBusWriteBytesRegResponse,
 };
-use i2cbus_api::server::MakeService;
+use ext::MakeService;
+
+// The bus operations are normally served by ext, which can also return a 403
+// if the policy forbids them.  If they reach the generated service, a 403
+// becomes an error.
+fn api_response<R, T>(rsp: R) -> std::result::Result<T, ApiError>
+where
+    R: Into<std::result::Result<T, ApiError>>,
+{
+    rsp.into()
+}
 
 impl<C> Api<C> for Server<C>
 where
-    C: Has<XSpanIdString>,
+    C: Has<XSpanIdString> + Has<Option<Authorization>>,
 {
     fn i2c_bus_api(&self, _context: &C) -> Box<dyn Future<Item = I2cBusApiResponse, Error = ApiError> + Send> {
         Box::new(futures::future::ok(http::get_api()))
@@ -117,16 +160,23 @@ where
         &self,
         _context: &C,
     ) -> Box<dyn Future<Item = I2cBusListResponse, Error = ApiError> + Send> {
-        Box::new(futures::future::ok(http::get_buses()))
+        // Normally served by ext, which also returns the adapter names
+        let ext::I2cBusListResponse::OK(buses) = http::get_buses();
+        Box::new(futures::future::ok(I2cBusListResponse::OK(
+            buses.into_iter().map(From::from).collect(),
+        )))
     }
 
     fn i2c_bus_read_byte(
         &self,
         bus_id: i32,
         addr: i32,
-        _context: &C,
+        context: &C,
     ) -> Box<dyn Future<Item = I2cBusReadByteResponse, Error = ApiError> + Send> {
-        Box::new(futures::future::ok(http::read_byte(&bus_id.into(), &addr.into())))
+        let identity = auth::identity(context);
+        Box::new(http::blocking(move || http::read_byte(
+            &identity, &bus_id.into(), &addr.into(),
+        )).and_then(api_response))
     }
 
     fn i2c_bus_read_bytes(
@@ -134,11 +184,12 @@ where
         bus_id: i32,
         addr: i32,
         num_bytes: i32,
-        _context: &C,
+        context: &C,
     ) -> Box<dyn Future<Item = I2cBusReadBytesResponse, Error = ApiError> + Send> {
-        Box::new(futures::future::ok(http::read_bytes(
-            &bus_id.into(), &addr.into(), &num_bytes.into(),
-        )))
+        let identity = auth::identity(context);
+        Box::new(http::blocking(move || http::read_bytes(
+            &identity, &bus_id.into(), &addr.into(), &num_bytes.into(),
+        )).and_then(api_response))
     }
 
     fn i2c_bus_read_reg(
@@ -147,11 +198,12 @@ where
         addr: i32,
         reg: i32,
         num_bytes: i32,
-        _context: &C,
+        context: &C,
     ) -> Box<dyn Future<Item = I2cBusReadRegResponse, Error = ApiError> + Send> {
-        Box::new(futures::future::ok(http::read_reg(
-            &bus_id.into(), &addr.into(), &reg.into(), &num_bytes.into(),
-        )))
+        let identity = auth::identity(context);
+        Box::new(http::blocking(move || http::read_reg(
+            &identity, &bus_id.into(), &addr.into(), &reg.into(), &num_bytes.into(), &Default::default(),
+        )).and_then(api_response))
     }
 
     fn i2c_bus_write_byte(
@@ -159,11 +211,12 @@ where
         bus_id: i32,
         addr: i32,
         value: i32,
-        _context: &C,
+        context: &C,
     ) -> Box<dyn Future<Item = I2cBusWriteByteResponse, Error = ApiError> + Send> {
-        Box::new(futures::future::ok(http::write_byte(
-            &bus_id.into(), &addr.into(), &value.into(),
-        )))
+        let identity = auth::identity(context);
+        Box::new(http::blocking(move || http::write_byte(
+            &identity, &bus_id.into(), &addr.into(), &value.into(),
+        )).and_then(api_response))
     }
 
     fn i2c_bus_write_byte_reg(
@@ -172,11 +225,12 @@ where
         addr: i32,
         reg: i32,
         value: i32,
-        _context: &C,
+        context: &C,
     ) -> Box<dyn Future<Item = I2cBusWriteByteRegResponse, Error = ApiError> + Send> {
-        Box::new(futures::future::ok(http::write_byte_reg(
-            &bus_id.into(), &addr.into(), &reg.into(), &value.into(),
-        )))
+        let identity = auth::identity(context);
+        Box::new(http::blocking(move || http::write_byte_reg(
+            &identity, &bus_id.into(), &addr.into(), &reg.into(), &value.into(), &Default::default(),
+        )).and_then(api_response))
     }
 
     fn i2c_bus_write_bytes(
@@ -184,11 +238,12 @@ where
         bus_id: i32,
         addr: i32,
         values: models::Values,
-        _context: &C,
+        context: &C,
     ) -> Box<dyn Future<Item = I2cBusWriteBytesResponse, Error = ApiError> + Send> {
-        Box::new(futures::future::ok(http::write_bytes(
-            &bus_id.into(), &addr.into(), &values,
-        )))
+        let identity = auth::identity(context);
+        Box::new(http::blocking(move || http::write_bytes(
+            &identity, &bus_id.into(), &addr.into(), &values,
+        )).and_then(api_response))
     }
 
     fn i2c_bus_write_bytes_reg(
@@ -197,10 +252,11 @@ where
         addr: i32,
         reg: i32,
         values: models::Values,
-        _context: &C,
+        context: &C,
     ) -> Box<dyn Future<Item = I2cBusWriteBytesRegResponse, Error = ApiError> + Send> {
-        Box::new(futures::future::ok(http::write_bytes_reg(
-            &bus_id.into(), &addr.into(), &reg.into(), &values,
-        )))
+        let identity = auth::identity(context);
+        Box::new(http::blocking(move || http::write_bytes_reg(
+            &identity, &bus_id.into(), &addr.into(), &reg.into(), &values, &Default::default(),
+        )).and_then(api_response))
     }
 }
\ No newline at end of file
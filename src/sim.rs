@@ -0,0 +1,271 @@
+use super::funcs::{I2C_FUNC_10BIT_ADDR, I2C_FUNC_I2C, I2C_FUNC_SMBUS_ALL};
+use super::smbus::{SmbusData, SmbusOp, BLOCK_MAX};
+use super::{is_ten_bit, Backend, BusError, BusInfo, Msg};
+use nix::errno::Errno;
+use std::collections::HashMap;
+use std::io;
+
+// Size of a simulated device's register file, if not specified
+const DEF_REG_FILE_SIZE: usize = 256;
+
+// A simulated I2C device, consisting of a register file and a register
+// pointer.  Behaves like most simple I2C peripherals:
+// - The first byte of a write sets the register pointer, or for a device
+//   with more than 256 registers the first two bytes (most significant
+//   first), as for larger EEPROMs
+// - Subsequent bytes of a write are written starting at the register pointer
+// - Reads return data starting at the register pointer
+// - The register pointer auto-increments, wrapping at the end of the file
+pub(crate) struct SimDevice {
+    regs: Vec<u8>,
+    pointer: usize,
+    pointer_width: usize,
+}
+
+impl SimDevice {
+    pub(crate) fn new(size: usize) -> SimDevice {
+        SimDevice {
+            regs: vec![0; size],
+            pointer: 0,
+            pointer_width: if size > 256 { 2 } else { 1 },
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        // A zero length write (quick write) just ACKs, and a write too short
+        // to set the pointer is ignored
+        if data.len() >= self.pointer_width {
+            let (reg, values) = data.split_at(self.pointer_width);
+            let reg = reg.iter().fold(0, |reg, byte| (reg << 8) | *byte as usize);
+            self.pointer = reg % self.regs.len();
+            for value in values {
+                self.regs[self.pointer] = *value;
+                self.inc_pointer();
+            }
+        }
+    }
+
+    fn read(&mut self, data: &mut [u8]) {
+        for value in data.iter_mut() {
+            *value = self.regs[self.pointer];
+            self.inc_pointer();
+        }
+    }
+
+    fn read_vec(&mut self, reg: u8, len: usize) -> Vec<u8> {
+        self.write(&[reg]);
+        let mut data: Vec<u8> = vec![0; len];
+        self.read(&mut data);
+        data
+    }
+
+    // Block transfers are stored as they appear on the wire - a count
+    // followed by the data
+    fn write_block(&mut self, reg: u8, values: &[u8]) {
+        let mut data: Vec<u8> = vec![reg, values.len() as u8];
+        data.extend_from_slice(values);
+        self.write(&data);
+    }
+
+    fn read_block(&mut self, reg: u8) -> Vec<u8> {
+        let len = std::cmp::min(self.read_vec(reg, 1)[0] as usize, BLOCK_MAX);
+        let mut data: Vec<u8> = vec![0; len];
+        self.read(&mut data);
+        data
+    }
+
+    // Emulates an SMBus operation in terms of the register file
+    fn smbus(&mut self, op: &SmbusOp) -> SmbusData {
+        match op {
+            SmbusOp::Quick(_) => SmbusData::None,
+            SmbusOp::ReceiveByte => {
+                let mut data: Vec<u8> = vec![0; 1];
+                self.read(&mut data);
+                SmbusData::Byte(data[0])
+            }
+            SmbusOp::SendByte(value) => {
+                self.write(&[*value]);
+                SmbusData::None
+            }
+            SmbusOp::ReadByteData(reg) => SmbusData::Byte(self.read_vec(*reg, 1)[0]),
+            SmbusOp::WriteByteData(reg, value) => {
+                self.write(&[*reg, *value]);
+                SmbusData::None
+            }
+            SmbusOp::ReadWordData(reg) => {
+                let data = self.read_vec(*reg, 2);
+                SmbusData::Word(u16::from_le_bytes([data[0], data[1]]))
+            }
+            SmbusOp::WriteWordData(reg, value) => {
+                let [lsb, msb] = value.to_le_bytes();
+                self.write(&[*reg, lsb, msb]);
+                SmbusData::None
+            }
+            SmbusOp::ProcessCall(reg, value) => {
+                let [lsb, msb] = value.to_le_bytes();
+                self.write(&[*reg, lsb, msb]);
+                let data = self.read_vec(*reg, 2);
+                SmbusData::Word(u16::from_le_bytes([data[0], data[1]]))
+            }
+            SmbusOp::BlockRead(reg) => SmbusData::Block(self.read_block(*reg)),
+            SmbusOp::BlockWrite(reg, values) => {
+                self.write_block(*reg, values);
+                SmbusData::None
+            }
+            SmbusOp::BlockProcessCall(reg, values) => {
+                self.write_block(*reg, values);
+                SmbusData::Block(self.read_block(*reg))
+            }
+        }
+    }
+
+    fn inc_pointer(&mut self) {
+        self.pointer = (self.pointer + 1) % self.regs.len();
+    }
+}
+
+// A simulated I2C bus, hosting SimDevices at particular addresses (offset by
+// ADDR_TEN_BIT for 10-bit devices).  Any
+// message sent to an address without a device fails with ENXIO, as the
+// kernel does when a real device fails to ACK its address.
+pub(crate) struct SimBus {
+    devices: HashMap<u16, SimDevice>,
+}
+
+impl SimBus {
+    pub(crate) fn new() -> SimBus {
+        SimBus {
+            devices: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn add_device(&mut self, addr: u16, device: SimDevice) {
+        self.devices.insert(addr, device);
+    }
+}
+
+// The error returned when no device ACKs the address
+fn nack() -> BusError {
+    BusError::from(i2cdev2::linux::LinuxI2CError::from(nix::Error::Sys(
+        Errno::ENXIO,
+    )))
+}
+
+impl Backend for SimBus {
+    fn rdwr(&mut self, msgs: &mut Vec<Msg>) -> Result<i32, BusError> {
+        // Check every device is present before touching any of them, as the
+        // whole combined transaction is aborted on the first NACK
+        if msgs.iter().any(|msg| !self.devices.contains_key(&msg.full_addr())) {
+            return Err(nack());
+        }
+        for msg in msgs.iter_mut() {
+            let device = self.devices.get_mut(&msg.full_addr()).unwrap();
+            if msg.is_read() {
+                device.read(msg.data);
+            } else {
+                device.write(msg.data);
+            }
+        }
+        Ok(msgs.len() as i32)
+    }
+
+    fn smbus(&mut self, addr: u16, op: &SmbusOp) -> Result<SmbusData, BusError> {
+        match self.devices.get_mut(&addr) {
+            Some(device) => Ok(device.smbus(op)),
+            None => Err(nack()),
+        }
+    }
+
+    fn funcs(&mut self) -> Result<u64, BusError> {
+        Ok(I2C_FUNC_I2C | I2C_FUNC_10BIT_ADDR | I2C_FUNC_SMBUS_ALL)
+    }
+}
+
+fn parse_num(num: &str) -> Option<usize> {
+    let num = num.trim();
+    if num.starts_with("0x") || num.starts_with("0X") {
+        usize::from_str_radix(&num[2..], 16).ok()
+    } else {
+        num.parse::<usize>().ok()
+    }
+}
+
+fn parse_device(device: &str) -> Result<(u16, SimDevice), BusError> {
+    let mut parts = device.split(':');
+    let addr = parts.next().and_then(parse_num);
+    let size = match parts.next() {
+        Some(size) => parse_num(size),
+        None => Some(DEF_REG_FILE_SIZE),
+    };
+    match (addr, size, parts.next()) {
+        (Some(addr), Some(size), None)
+            if (addr <= 0x7f || (is_ten_bit(addr as u16) && addr <= 0xa3ff)) && size > 0 =>
+        {
+            Ok((addr as u16, SimDevice::new(size)))
+        }
+        _ => Err(BusError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid simulated device {}", device),
+        ))),
+    }
+}
+
+/// Returns simulated buses as described by the spec string, which is of the
+/// form:
+///
+///   bus[;bus...]
+///
+/// where each bus is a comma separated list of devices, each of the form
+/// addr[:size], with addr and size in decimal or 0x prefixed hex, and 10-bit
+/// addresses offset by 0xa000 (e.g. 0xa150 for 10-bit address 0x150).  For
+/// example "0x20,0x50:128;0x68" creates bus 0 with devices at 0x20 and 0x50
+/// (with a 128 byte register file), and bus 1 with a device at 0x68.
+/// Devices with more than 256 registers take 2 byte register pointers.
+pub(crate) fn init_buses(spec: &str) -> Result<Vec<BusInfo>, BusError> {
+    let mut buses: Vec<BusInfo> = Vec::new();
+    for (id, bus_spec) in spec.split(';').enumerate() {
+        let mut bus = SimBus::new();
+        for device in bus_spec.split(',').filter(|d| !d.trim().is_empty()) {
+            let (addr, device) = parse_device(device)?;
+            bus.add_device(addr, device);
+        }
+        let path = format!("sim-{}", id);
+        let name = Some(format!("Simulated I2C bus {}", id));
+        buses.push(BusInfo::with_backend(id, path, name, Box::new(bus)));
+    }
+    Ok(buses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spec() {
+        let buses = init_buses("0x20,0x50:128;;0xa150").unwrap();
+        assert_eq!(buses.len(), 3);
+        assert_eq!(buses[1].path, "sim-1");
+        let mut bus = buses[0].lock();
+        assert!(bus.write_quick(0x20).is_ok());
+        assert!(bus.write_quick(0x21).is_err());
+        assert!(buses[2].lock().write_quick(0xa150).is_ok());
+        assert!(buses[2].lock().write_quick(0x50).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_devices() {
+        for spec in &["0x80", "0x20:0", "0x20:16:1", "0xb000", "bus"] {
+            assert!(init_buses(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn smbus_ops() {
+        let mut device = SimDevice::new(256);
+        device.smbus(&SmbusOp::WriteWordData(0x10, 0x1234));
+        assert_eq!(device.smbus(&SmbusOp::ReadByteData(0x10)), SmbusData::Byte(0x34));
+        assert_eq!(device.smbus(&SmbusOp::ReadWordData(0x10)), SmbusData::Word(0x1234));
+        device.smbus(&SmbusOp::BlockWrite(0x20, vec![1, 2, 3]));
+        assert_eq!(device.smbus(&SmbusOp::BlockRead(0x20)), SmbusData::Block(vec![1, 2, 3]));
+    }
+}
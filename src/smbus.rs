@@ -0,0 +1,229 @@
+use super::funcs::*;
+use super::{is_ten_bit, BusError, ADDR_TEN_BIT};
+use nix::{convert_ioctl_res, ioctl_write_int_bad, ioctl_write_ptr_bad};
+use std::os::unix::io::RawFd;
+
+// Max number of data bytes in an SMBus block transfer
+pub(crate) const BLOCK_MAX: usize = 32;
+
+// An SMBus protocol operation, with any command (register) and data to send
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SmbusOp {
+    // Sends just the Rd/Wr bit - true for read
+    Quick(bool),
+
+    // Receives a byte without sending a command
+    ReceiveByte,
+
+    // Sends a byte without a command
+    SendByte(u8),
+
+    // Sends a command, then receives a byte
+    ReadByteData(u8),
+
+    // Sends a command then a byte
+    WriteByteData(u8, u8),
+
+    // Sends a command, then receives a word (LSB first)
+    ReadWordData(u8),
+
+    // Sends a command then a word (LSB first)
+    WriteWordData(u8, u16),
+
+    // Sends a command and a word, then receives a word
+    ProcessCall(u8, u16),
+
+    // Sends a command, then receives a count followed by that many bytes
+    BlockRead(u8),
+
+    // Sends a command, a count and that many bytes
+    BlockWrite(u8, Vec<u8>),
+
+    // Sends a command, a count and that many bytes, then receives a count
+    // followed by that many bytes
+    BlockProcessCall(u8, Vec<u8>),
+}
+
+impl SmbusOp {
+    // Returns the I2C_FUNC_* flag the adapter must support for this operation
+    pub(crate) fn funcs(&self) -> u64 {
+        match self {
+            SmbusOp::Quick(_) => I2C_FUNC_SMBUS_QUICK,
+            SmbusOp::ReceiveByte => I2C_FUNC_SMBUS_READ_BYTE,
+            SmbusOp::SendByte(_) => I2C_FUNC_SMBUS_WRITE_BYTE,
+            SmbusOp::ReadByteData(_) => I2C_FUNC_SMBUS_READ_BYTE_DATA,
+            SmbusOp::WriteByteData(_, _) => I2C_FUNC_SMBUS_WRITE_BYTE_DATA,
+            SmbusOp::ReadWordData(_) => I2C_FUNC_SMBUS_READ_WORD_DATA,
+            SmbusOp::WriteWordData(_, _) => I2C_FUNC_SMBUS_WRITE_WORD_DATA,
+            SmbusOp::ProcessCall(_, _) => I2C_FUNC_SMBUS_PROC_CALL,
+            SmbusOp::BlockRead(_) => I2C_FUNC_SMBUS_READ_BLOCK_DATA,
+            SmbusOp::BlockWrite(_, _) => I2C_FUNC_SMBUS_WRITE_BLOCK_DATA,
+            SmbusOp::BlockProcessCall(_, _) => I2C_FUNC_SMBUS_BLOCK_PROC_CALL,
+        }
+    }
+}
+
+// Data returned by an SMBus operation
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SmbusData {
+    None,
+    Byte(u8),
+    Word(u16),
+    Block(Vec<u8>),
+}
+
+// Kernel interface, from linux/i2c.h and linux/i2c-dev.h
+
+const I2C_SLAVE: u16 = 0x0703;
+const I2C_TENBIT: u16 = 0x0704;
+const I2C_SMBUS: u16 = 0x0720;
+
+const I2C_SMBUS_READ: u8 = 1;
+const I2C_SMBUS_WRITE: u8 = 0;
+
+const I2C_SMBUS_QUICK: u32 = 0;
+const I2C_SMBUS_BYTE: u32 = 1;
+const I2C_SMBUS_BYTE_DATA: u32 = 2;
+const I2C_SMBUS_WORD_DATA: u32 = 3;
+const I2C_SMBUS_PROC_CALL: u32 = 4;
+const I2C_SMBUS_BLOCK_DATA: u32 = 5;
+const I2C_SMBUS_BLOCK_PROC_CALL: u32 = 7;
+
+// union i2c_smbus_data - block[0] holds the length for block transfers, and
+// the union is one byte larger than needed for user-space compatibility
+#[repr(C)]
+struct i2c_smbus_data {
+    block: [u8; BLOCK_MAX + 2],
+}
+
+#[repr(C)]
+struct i2c_smbus_ioctl_data {
+    read_write: u8,
+    command: u8,
+    size: u32,
+    data: *mut i2c_smbus_data,
+}
+
+ioctl_write_int_bad!(i2c_set_slave_address, I2C_SLAVE);
+ioctl_write_int_bad!(i2c_set_tenbit, I2C_TENBIT);
+ioctl_write_ptr_bad!(i2c_smbus, I2C_SMBUS, i2c_smbus_ioctl_data);
+
+impl i2c_smbus_data {
+    fn new() -> i2c_smbus_data {
+        i2c_smbus_data {
+            block: [0; BLOCK_MAX + 2],
+        }
+    }
+
+    fn with_byte(byte: u8) -> i2c_smbus_data {
+        let mut data = i2c_smbus_data::new();
+        data.block[0] = byte;
+        data
+    }
+
+    fn with_word(word: u16) -> i2c_smbus_data {
+        let mut data = i2c_smbus_data::new();
+        data.block[..2].copy_from_slice(&word.to_ne_bytes());
+        data
+    }
+
+    fn with_block(values: &[u8]) -> i2c_smbus_data {
+        let mut data = i2c_smbus_data::new();
+        data.block[0] = values.len() as u8;
+        data.block[1..=values.len()].copy_from_slice(values);
+        data
+    }
+
+    fn byte(&self) -> u8 {
+        self.block[0]
+    }
+
+    fn word(&self) -> u16 {
+        u16::from_ne_bytes([self.block[0], self.block[1]])
+    }
+
+    fn block(&self) -> Vec<u8> {
+        let len = std::cmp::min(self.block[0] as usize, BLOCK_MAX);
+        self.block[1..=len].to_vec()
+    }
+}
+
+// Carries out an SMBus operation on the device at addr, using the I2C_SMBUS
+// ioctl on the bus's file descriptor.  A 10-bit addr (offset by ADDR_TEN_BIT)
+// selects 10-bit addressing via I2C_TENBIT.  Fails with EBUSY if a kernel
+// driver has claimed the device.
+pub(crate) fn smbus(fd: RawFd, addr: u16, op: &SmbusOp) -> Result<SmbusData, BusError> {
+    match op {
+        SmbusOp::BlockWrite(_, values) | SmbusOp::BlockProcessCall(_, values)
+            if values.len() > BLOCK_MAX =>
+        {
+            return Err(BusError::from(std::io::Error::from_raw_os_error(
+                nix::errno::Errno::EINVAL as i32,
+            )))
+        }
+        _ => (),
+    }
+
+    let (read_write, command, size, mut data) = match op {
+        SmbusOp::Quick(read) => {
+            let read_write = if *read { I2C_SMBUS_READ } else { I2C_SMBUS_WRITE };
+            (read_write, 0, I2C_SMBUS_QUICK, i2c_smbus_data::new())
+        }
+        SmbusOp::ReceiveByte => (I2C_SMBUS_READ, 0, I2C_SMBUS_BYTE, i2c_smbus_data::new()),
+        SmbusOp::SendByte(value) => (I2C_SMBUS_WRITE, *value, I2C_SMBUS_BYTE, i2c_smbus_data::new()),
+        SmbusOp::ReadByteData(reg) => (I2C_SMBUS_READ, *reg, I2C_SMBUS_BYTE_DATA, i2c_smbus_data::new()),
+        SmbusOp::WriteByteData(reg, value) => (
+            I2C_SMBUS_WRITE,
+            *reg,
+            I2C_SMBUS_BYTE_DATA,
+            i2c_smbus_data::with_byte(*value),
+        ),
+        SmbusOp::ReadWordData(reg) => (I2C_SMBUS_READ, *reg, I2C_SMBUS_WORD_DATA, i2c_smbus_data::new()),
+        SmbusOp::WriteWordData(reg, value) => (
+            I2C_SMBUS_WRITE,
+            *reg,
+            I2C_SMBUS_WORD_DATA,
+            i2c_smbus_data::with_word(*value),
+        ),
+        SmbusOp::ProcessCall(reg, value) => (
+            I2C_SMBUS_WRITE,
+            *reg,
+            I2C_SMBUS_PROC_CALL,
+            i2c_smbus_data::with_word(*value),
+        ),
+        SmbusOp::BlockRead(reg) => (I2C_SMBUS_READ, *reg, I2C_SMBUS_BLOCK_DATA, i2c_smbus_data::new()),
+        SmbusOp::BlockWrite(reg, values) => (
+            I2C_SMBUS_WRITE,
+            *reg,
+            I2C_SMBUS_BLOCK_DATA,
+            i2c_smbus_data::with_block(values),
+        ),
+        SmbusOp::BlockProcessCall(reg, values) => (
+            I2C_SMBUS_WRITE,
+            *reg,
+            I2C_SMBUS_BLOCK_PROC_CALL,
+            i2c_smbus_data::with_block(values),
+        ),
+    };
+
+    let args = i2c_smbus_ioctl_data {
+        read_write,
+        command,
+        size,
+        data: &mut data,
+    };
+    let ten_bit = is_ten_bit(addr);
+    let addr = if ten_bit { addr & !ADDR_TEN_BIT } else { addr };
+    unsafe {
+        i2c_set_tenbit(fd, i32::from(ten_bit)).map_err(i2cdev2::linux::LinuxI2CError::from)?;
+        i2c_set_slave_address(fd, i32::from(addr)).map_err(i2cdev2::linux::LinuxI2CError::from)?;
+        i2c_smbus(fd, &args).map_err(i2cdev2::linux::LinuxI2CError::from)?;
+    }
+
+    Ok(match op {
+        SmbusOp::ReceiveByte | SmbusOp::ReadByteData(_) => SmbusData::Byte(data.byte()),
+        SmbusOp::ReadWordData(_) | SmbusOp::ProcessCall(_, _) => SmbusData::Word(data.word()),
+        SmbusOp::BlockRead(_) | SmbusOp::BlockProcessCall(_, _) => SmbusData::Block(data.block()),
+        _ => SmbusData::None,
+    })
+}
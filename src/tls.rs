@@ -0,0 +1,110 @@
+//! TLS support - building the SSL acceptor, reloading it on SIGHUP, and
+//! passing the client certificate of a mutual TLS connection to each request.
+
+use arc_swap::ArcSwap;
+use hyper::{Body, Request};
+use log::{info, warn};
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslAcceptor, SslVerifyMode};
+use openssl::x509::{X509Name, X509NameRef};
+use signal_hook::iterator::Signals;
+use signal_hook::SIGHUP;
+use std::env;
+use std::sync::Arc;
+use std::thread;
+
+// Environment variable which, if set, is the path to a bundle of CA
+// certificates used to verify client certificates.  Clients must then
+// present a certificate signed by one of these CAs (mutual TLS).
+pub(crate) const SSL_CLIENT_CA_VAR: &str = "SSL_CLIENT_CA";
+
+// Builds the SSL acceptor, using the key and certificate from SSL_KEY and
+// SSL_CERT (see httpd_util::ssl), and, if SSL_CLIENT_CA is set, requiring
+// and verifying client certificates
+pub(crate) fn acceptor() -> Result<SslAcceptor, ErrorStack> {
+    let mut ssl = httpd_util::ssl()?;
+    if let Ok(ca) = env::var(SSL_CLIENT_CA_VAR) {
+        info!("Verifying client certificates using {}", ca);
+        ssl.set_ca_file(&ca)?;
+        ssl.set_client_ca_list(X509Name::load_client_ca_file(&ca)?);
+        ssl.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    Ok(ssl.build())
+}
+
+// Starts a thread which rebuilds the SSL acceptor whenever SIGHUP is
+// received, so renewed certificates can be picked up without restarting.
+// Existing connections are unaffected, and new connections use the new
+// certificates.  If the new certificates can't be loaded, the old ones
+// continue to be used.
+pub(crate) fn reload_on_sighup(acceptor: Arc<ArcSwap<SslAcceptor>>) {
+    let signals = match Signals::new([SIGHUP]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("Failed to register for SIGHUP - won't reload certificates {}", e);
+            return;
+        }
+    };
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            match self::acceptor() {
+                Ok(new) => {
+                    info!("SIGHUP caught - reloaded SSL certificates");
+                    acceptor.store(Arc::new(new));
+                }
+                Err(e) => warn!("SIGHUP caught - failed to reload SSL certificates {}", e),
+            }
+        }
+    });
+}
+
+/// The subject of the certificate the client presented, for mutual TLS
+/// connections, e.g. "CN=client, O=Example".  Added to the extensions of
+/// each request made over the connection.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ClientCert(pub String);
+
+impl ClientCert {
+    pub(crate) fn new(name: &X509NameRef) -> ClientCert {
+        let subject = name
+            .entries()
+            .map(|entry| {
+                let field = entry.object().nid().short_name().unwrap_or("?");
+                let value = String::from_utf8_lossy(entry.data().as_slice());
+                format!("{}={}", field, value)
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        ClientCert(subject)
+    }
+}
+
+/// Wraps the per-connection service, adding the connection's ClientCert (if
+/// any) to each request
+pub(crate) struct AddClientCert<T> {
+    inner: T,
+    cert: Option<ClientCert>,
+}
+
+impl<T> AddClientCert<T> {
+    pub(crate) fn new(inner: T, cert: Option<ClientCert>) -> Self {
+        AddClientCert { inner, cert }
+    }
+}
+
+impl<T> hyper::service::Service for AddClientCert<T>
+where
+    T: hyper::service::Service<ReqBody = Body>,
+{
+    type ReqBody = Body;
+    type ResBody = T::ResBody;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&mut self, mut req: Request<Self::ReqBody>) -> Self::Future {
+        if let Some(cert) = &self.cert {
+            req.extensions_mut().insert(cert.clone());
+        }
+        self.inner.call(req)
+    }
+}